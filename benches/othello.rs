@@ -1,7 +1,7 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use rusty_othello_ai::{
     mcts::MCTS,
-    othello::{simulate_game, State},
+    othello::{simulate_game, Color, State},
 };
 use std::time::Duration;
 
@@ -10,9 +10,9 @@ pub fn bench_simulate_game(c: &mut Criterion) {
     group
         .sample_size(1000)
         .measurement_time(Duration::from_secs(10));
-    let game_state = rusty_othello_ai::othello::State::new();
+    let game_state: State = State::new();
     group.bench_function("simulate game 1", |b| {
-        b.iter(|| simulate_game(black_box(&mut game_state.clone())))
+        b.iter(|| simulate_game(black_box(&game_state)))
     });
 
     group.finish()
@@ -22,7 +22,7 @@ pub fn bench_mcts_search(c: &mut Criterion) {
     group
         .sample_size(1000)
         .measurement_time(Duration::from_secs(10));
-    let mut mcts = MCTS::new("true", 1.0);
+    let mut mcts: MCTS = MCTS::new(Color::WHITE, 1.0);
     group.bench_function("Monte Carlo Tree Search", |b| {
         b.iter(|| mcts.search(State::new(), 10, |_, _, _| {}))
     });
@@ -1,50 +1,55 @@
-use rusty_othello_ai::mcts::MCTS;
-use rusty_othello_ai::othello::{caculate_win, Color, State};
-use std::isize;
+// Keeps the older scripts running `ai-test <a> <b> [size] [position|-] [handicap]` working
+// It plays one self-play game in which a plays white and b black, and prints only the win balance
+use rusty_othello_ai::selfplay::{selfplay, PlayerSettings, SelfplayConfig};
+use std::process::exit;
 
 pub fn main() {
-    let args: Vec<String> = std::env::args().collect();
-    let mut win_balance: isize = 0;
-    let a: f32 = args
-        .get(1)
-        .expect("Missing value for A")
-        .parse()
-        .expect("Not a valid floatingpoint number");
-    let b: f32 = args
-        .get(2)
-        .expect("Missing value for A")
-        .parse()
-        .expect("Not a valid floatingpoint number");
-
-    let mut state = State::new();
-    let mut mcts = MCTS::new("true", a);
-    let mut mcts2 = MCTS::new("false", b);
-    let mut ai_iterations = 500;
-    loop {
-        state = ai_turn(&mut mcts, state.clone(), ai_iterations);
-        if state.remaining_moves == 0 {
-            break;
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let config = match config(&args) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{e}");
+            eprintln!(
+                "Usage: ai-test <a explore (white)> <b explore (black)> [size] [position|-] [handicap]"
+            );
+            exit(2);
         }
-        state = ai_turn(&mut mcts2, state.clone(), ai_iterations);
-        if state.remaining_moves == 0 {
-            break;
+    };
+    eprintln!("ai-test is deprecated, use `rusty_othello_ai selfplay` instead");
+    match selfplay(&config, |_, _| {}) {
+        Ok(results) => println!("{}", results.win_balance()),
+        Err(e) => {
+            eprintln!("{e}");
+            exit(1);
         }
-        ai_iterations += ai_iterations / 100;
     }
-    win_balance += match caculate_win(state) {
-        Some(Color::WHITE) => 1,
-        Some(Color::BLACK) => -1,
-        None => 0,
-    };
-    println!("{win_balance}")
 }
 
-fn ai_turn(mcts: &mut MCTS, state: State, iterations: usize) -> State {
-    let dev_null = |_a: usize, _b: usize, _c: &Color| -> () {};
-    let action = mcts.search(state.clone(), iterations, dev_null);
-    if action.is_ok() {
-        state.clone().do_action(Some(action.unwrap().clone()))
-    } else {
-        state.clone().do_action(None)
-    }
+fn config(args: &[String]) -> Result<SelfplayConfig, String> {
+    let explore = |index: usize, name: &str| -> Result<f32, String> {
+        let value = args.get(index).ok_or(format!("Missing value for {name}"))?;
+        value
+            .parse()
+            .map_err(|_| format!("{value} isn't a valid floating point number"))
+    };
+    let size = match args.get(2) {
+        Some(size) => size
+            .parse()
+            .map_err(|_| format!("{size} isn't a board size"))?,
+        None => 8,
+    };
+    Ok(SelfplayConfig {
+        white: PlayerSettings {
+            explore: explore(0, "A")?,
+            ..PlayerSettings::default()
+        },
+        black: PlayerSettings {
+            explore: explore(1, "B")?,
+            ..PlayerSettings::default()
+        },
+        size,
+        position: args.get(3).filter(|p| *p != "-").cloned(),
+        handicap: args.get(4).cloned(),
+        ..SelfplayConfig::default()
+    })
 }
@@ -0,0 +1,550 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use rusty_othello_ai::client::{GameServerClient, RetryPolicy, DEFAULT_SERVER_URL, SERVER_URL_ENV};
+use rusty_othello_ai::mcts::MCTS;
+use rusty_othello_ai::mlp::Mlp;
+use rusty_othello_ai::othello::{starting_state, Color, State};
+use rusty_othello_ai::selection::{CornerPrior, SelectionPolicy, DEFAULT_RAVE_EQUIVALENCE};
+use rusty_othello_ai::selfplay::{PlayerSettings, SelfplayConfig};
+use rusty_othello_ai::time_manager::{SearchLimit, TimeManager};
+
+use crate::console_game::{Difficulty, GameMode};
+
+// Commands accepted in place of a subcommand by older scripts, which passed the AI's color first
+const LEGACY_COLORS: [&str; 8] = ["false", "0", "b", "black", "true", "1", "w", "white"];
+
+#[derive(Debug, Parser)]
+#[command(version, about = "Othello AI using Monte Carlo Tree Search")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+impl Cli {
+    pub fn from_env() -> Cli {
+        Cli::parse_from(with_subcommand(std::env::args().collect()))
+    }
+}
+
+// Rewrites the arguments of older scripts, "<color> [options]" means "play-server --color <color> [options]"
+fn with_subcommand(mut args: Vec<String>) -> Vec<String> {
+    if let Some(first) = args.get(1) {
+        if LEGACY_COLORS.contains(&first.to_lowercase().as_str()) {
+            let color = args.remove(1);
+            args.splice(
+                1..1,
+                ["play-server".to_string(), "--color".to_string(), color],
+            );
+        }
+    }
+    args
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    #[command(about = "Play one side of a game on the game server")]
+    PlayServer(PlayServerArgs),
+    #[command(about = "Play in the terminal against the AI, another player or watch two AIs")]
+    Console(ConsoleArgs),
+    #[command(about = "Let two AIs play each other and report the win balance")]
+    Selfplay(SelfplayArgs),
+    #[command(about = "Search a position and print the statistics of its moves")]
+    Analyze(AnalyzeArgs),
+    #[command(about = "Measure the search speed in iterations per second")]
+    Bench(BenchArgs),
+    #[command(about = "Host games for clients of the game server protocol")]
+    Serve(ServeArgs),
+    #[command(about = "Play through the text engine protocol on stdin and stdout")]
+    Engine(EngineArgs),
+}
+
+// A side of the board, the server protocol's "false" and "true" are accepted as well
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Side {
+    #[value(alias = "b", alias = "false", alias = "0")]
+    Black,
+    #[value(alias = "w", alias = "true", alias = "1")]
+    White,
+}
+impl From<Side> for Color {
+    fn from(side: Side) -> Color {
+        match side {
+            Side::Black => Color::BLACK,
+            Side::White => Color::WHITE,
+        }
+    }
+}
+
+// The selection policy of a search, the policies using priors weigh the moves with `CornerPrior`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Selection {
+    Ucb1,
+    Ucb1Tuned,
+    Puct,
+    ProgressiveBias,
+}
+impl Selection {
+    pub fn policy(self) -> SelectionPolicy {
+        match self {
+            Selection::Ucb1 => SelectionPolicy::Ucb1,
+            Selection::Ucb1Tuned => SelectionPolicy::Ucb1Tuned,
+            Selection::Puct => SelectionPolicy::Puct,
+            Selection::ProgressiveBias => SelectionPolicy::ProgressiveBias,
+        }
+    }
+
+    pub fn apply<const N: usize>(self, mcts: MCTS<N>) -> MCTS<N> {
+        let policy = self.policy();
+        match policy.uses_priors() {
+            true => mcts.with_selection(policy).with_prior(CornerPrior),
+            false => mcts.with_selection(policy),
+        }
+    }
+}
+
+// Keeps RAVE statistics in the search if enabled
+pub fn with_rave<const N: usize>(mcts: MCTS<N>, rave: bool) -> MCTS<N> {
+    match rave {
+        true => mcts.with_rave(DEFAULT_RAVE_EQUIVALENCE),
+        false => mcts,
+    }
+}
+
+// Simulates the games of the leaves on a thread pool unless a single game is played on one thread
+pub fn with_leaf_parallel<const N: usize>(
+    mcts: MCTS<N>,
+    playouts: usize,
+    threads: usize,
+) -> MCTS<N> {
+    match (playouts, threads) {
+        (1, 1) => mcts,
+        _ => mcts.with_leaf_parallel(playouts, threads),
+    }
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct PositionArgs {
+    #[arg(
+        long,
+        help = "Start from an encoded position instead of the standard opening"
+    )]
+    pub position: Option<String>,
+    #[arg(
+        long,
+        value_name = "COLOR:DISCS",
+        help = "Give a side extra discs in the corners"
+    )]
+    pub handicap: Option<String>,
+}
+impl PositionArgs {
+    pub fn state<const N: usize>(&self) -> Result<State<N>, String> {
+        starting_state(self.position.as_deref(), self.handicap.as_deref())
+            .map_err(|e| format!("Invalid starting position: {e}"))
+    }
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct SearchArgs {
+    #[arg(
+        long,
+        conflicts_with = "time",
+        help = "Search iterations for the first move, growing by 2% every move"
+    )]
+    pub iterations: Option<usize>,
+    #[arg(long, value_name = "SECONDS", value_parser = seconds, help = "Play on a game clock with this much time")]
+    pub time: Option<Duration>,
+    #[arg(long, value_name = "SECONDS", value_parser = seconds, default_value = "0", help = "Time added to the clock after every move")]
+    pub increment: Duration,
+    #[arg(
+        long,
+        default_value_t = 1.0,
+        help = "Exploration constant of the search"
+    )]
+    pub explore: f32,
+    #[arg(
+        long,
+        value_enum,
+        help = "How the search selects the moves to explore [default: puct with a network, ucb1 otherwise]"
+    )]
+    pub selection: Option<Selection>,
+    #[arg(
+        long,
+        help = "Blend all-moves-as-first (RAVE) statistics into the selection"
+    )]
+    pub rave: bool,
+    #[arg(long, default_value_t = 1, value_parser = positive, help = "Random games simulated for every expanded leaf")]
+    pub playouts: usize,
+    #[arg(long, default_value_t = 1, value_parser = positive, help = "Threads simulating the games of the leaves")]
+    pub threads: usize,
+    #[arg(long, default_value_t = 1, value_parser = positive, help = "Leaves collected with virtual losses and evaluated together in every iteration")]
+    pub batch: usize,
+    #[arg(long, help = "Seed the search's random moves to repeat its choices")]
+    pub seed: Option<u64>,
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["playouts", "threads"], help = "Value and move priors from the network weights in this JSON file")]
+    pub network: Option<PathBuf>,
+    #[arg(long, default_value_t = 0.0, value_parser = unit, requires = "network", help = "Share of the random game in the value of a leaf evaluated by the network")]
+    pub rollout_weight: f32,
+}
+impl SearchArgs {
+    // The limit of every move's search, the given number of iterations if neither is set
+    pub fn limit(&self, default_iterations: usize) -> SearchLimit {
+        match (self.time, self.iterations) {
+            (Some(time), _) => SearchLimit::Clock(TimeManager::new(time, self.increment)),
+            (None, iterations) => SearchLimit::Iterations(iterations.unwrap_or(default_iterations)),
+        }
+    }
+
+    // The selection policy, PUCT by default with a network to use its priors
+    pub fn selection(&self) -> Selection {
+        match (self.selection, &self.network) {
+            (Some(selection), _) => selection,
+            (None, Some(_)) => Selection::Puct,
+            (None, None) => Selection::Ucb1,
+        }
+    }
+
+    // The network replaces the random games and the prior of the selection when given
+    pub fn mcts<const N: usize>(&self, color: Color) -> Result<MCTS<N>, String> {
+        let selection = self.selection();
+        if self.network.is_some() && matches!(selection, Selection::Ucb1 | Selection::Ucb1Tuned) {
+            eprintln!("Warning: the network's move priors are only used by --selection puct or progressive-bias");
+        }
+        let mcts = with_rave(selection.apply(MCTS::new(color, self.explore)), self.rave);
+        let mcts = match self.seed {
+            Some(seed) => mcts.with_seed(seed),
+            None => mcts,
+        }
+        .with_batch_size(self.batch);
+        match &self.network {
+            Some(path) => {
+                let network: Mlp<N> =
+                    Mlp::load(path).map_err(|e| format!("{}: {e}", path.display()))?;
+                Ok(mcts.with_state_evaluator(network, self.rollout_weight))
+            }
+            None => Ok(with_leaf_parallel(mcts, self.playouts, self.threads)),
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct PlayServerArgs {
+    #[arg(long, value_enum, help = "The color the AI plays")]
+    pub color: Side,
+    #[arg(long, env = SERVER_URL_ENV, default_value = DEFAULT_SERVER_URL, help = "Base URL of the game server")]
+    pub server: String,
+    #[arg(long, value_name = "SECONDS", value_parser = seconds, help = "Timeout of every request")]
+    pub timeout: Option<Duration>,
+    #[arg(long, help = "Retries of a failed request before giving up")]
+    pub retries: Option<usize>,
+    #[arg(
+        long,
+        help = "Poll for the turn instead of waiting for it with long polling"
+    )]
+    pub poll: bool,
+    #[command(flatten)]
+    pub search: SearchArgs,
+    #[command(flatten)]
+    pub position: PositionArgs,
+}
+impl PlayServerArgs {
+    pub fn client(&self) -> GameServerClient {
+        let mut retry = RetryPolicy::default();
+        if let Some(retries) = self.retries {
+            retry.max_retries = Some(retries);
+        }
+        let client = GameServerClient::new(&self.server)
+            .with_retry_policy(retry)
+            .with_long_polling(!self.poll);
+        match self.timeout {
+            Some(timeout) => client.with_timeout(timeout),
+            None => client,
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct ConsoleArgs {
+    #[arg(
+        long,
+        value_enum,
+        default_value = "player-ai",
+        help = "Who controls the two sides"
+    )]
+    pub mode: GameMode,
+    #[arg(
+        long,
+        value_enum,
+        default_value = "black",
+        help = "The player's color against the AI"
+    )]
+    pub color: Side,
+    #[arg(long, value_enum, default_value = "hard", help = "Strength of the AI")]
+    pub level: Difficulty,
+    #[arg(
+        long,
+        default_value_t = 1.0,
+        help = "Exploration constant of the AI's search"
+    )]
+    pub explore: f32,
+    #[arg(long, help = "Seed the AI's random moves to repeat its choices")]
+    pub seed: Option<u64>,
+    #[command(flatten)]
+    pub position: PositionArgs,
+    #[arg(
+        long,
+        help = "Ask for the mode, color, level and exploration interactively"
+    )]
+    pub setup: bool,
+    #[arg(long, help = "Play in the full screen terminal UI")]
+    pub tui: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct SelfplayArgs {
+    #[arg(long, default_value_t = 1, help = "Number of games to play")]
+    pub games: usize,
+    #[arg(
+        long,
+        default_value_t = 500,
+        help = "Search iterations for the first move, growing by 1% every round"
+    )]
+    pub iterations: usize,
+    #[arg(
+        long,
+        default_value_t = 1.0,
+        help = "Exploration constant of black's search"
+    )]
+    pub black_explore: f32,
+    #[arg(
+        long,
+        default_value_t = 1.0,
+        help = "Exploration constant of white's search"
+    )]
+    pub white_explore: f32,
+    #[arg(
+        long,
+        value_enum,
+        default_value = "ucb1",
+        help = "Selection policy of black's search"
+    )]
+    pub black_selection: Selection,
+    #[arg(
+        long,
+        value_enum,
+        default_value = "ucb1",
+        help = "Selection policy of white's search"
+    )]
+    pub white_selection: Selection,
+    #[arg(long, help = "Blend RAVE statistics into black's selection")]
+    pub black_rave: bool,
+    #[arg(long, help = "Blend RAVE statistics into white's selection")]
+    pub white_rave: bool,
+    #[arg(
+        long,
+        conflicts_with_all = ["white_explore", "white_selection", "white_rave"],
+        help = "Search for both sides in one tree with black's settings"
+    )]
+    pub shared_tree: bool,
+    #[arg(long, default_value_t = 8, value_parser = board_size, help = "Board size, 6, 8 or 10")]
+    pub size: usize,
+    #[arg(long, default_value_t = 1, value_parser = positive, help = "Games played at the same time")]
+    pub threads: usize,
+    #[arg(
+        long,
+        help = "Seed of the first game, every following game uses the next seed"
+    )]
+    pub seed: Option<u64>,
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Append the positions of the games as training data to this JSON lines file, skipping the games already in it"
+    )]
+    pub output: Option<PathBuf>,
+    #[arg(
+        long,
+        requires = "output",
+        help = "Write every position in all 8 rotations and reflections of the board"
+    )]
+    pub symmetries: bool,
+    #[command(flatten)]
+    pub position: PositionArgs,
+}
+impl SelfplayArgs {
+    pub fn config(&self) -> SelfplayConfig {
+        SelfplayConfig {
+            games: self.games,
+            iterations: self.iterations,
+            black: PlayerSettings {
+                explore: self.black_explore,
+                selection: self.black_selection.policy(),
+                rave: self.black_rave,
+            },
+            white: PlayerSettings {
+                explore: self.white_explore,
+                selection: self.white_selection.policy(),
+                rave: self.white_rave,
+            },
+            shared_tree: self.shared_tree,
+            size: self.size,
+            threads: self.threads,
+            seed: self.seed,
+            output: self.output.clone(),
+            symmetries: self.symmetries,
+            position: self.position.position.clone(),
+            handicap: self.position.handicap.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct AnalyzeArgs {
+    #[arg(long, default_value_t = 5, help = "Number of moves listed")]
+    pub moves: usize,
+    #[command(flatten)]
+    pub search: SearchArgs,
+    #[command(flatten)]
+    pub position: PositionArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct BenchArgs {
+    #[arg(long, default_value_t = 1, value_parser = positive, help = "Searches run at the same time, every following search uses the next seed")]
+    pub searches: usize,
+    #[command(flatten)]
+    pub search: SearchArgs,
+    #[command(flatten)]
+    pub position: PositionArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct ServeArgs {
+    #[arg(long, default_value = "localhost:8181", help = "Address to listen on")]
+    pub addr: String,
+    #[arg(
+        long,
+        value_enum,
+        help = "Let the server play random moves for this color in the default game"
+    )]
+    pub opponent: Option<Side>,
+    #[command(flatten)]
+    pub position: PositionArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct EngineArgs {
+    #[command(flatten)]
+    pub search: SearchArgs,
+}
+
+fn seconds(value: &str) -> Result<Duration, String> {
+    let seconds: f64 = value
+        .parse()
+        .map_err(|_| format!("{value} isn't a number of seconds"))?;
+    Duration::try_from_secs_f64(seconds).map_err(|e| e.to_string())
+}
+
+fn board_size(value: &str) -> Result<usize, String> {
+    match value {
+        "6" => Ok(6),
+        "8" => Ok(8),
+        "10" => Ok(10),
+        _ => Err(format!("Unsupported board size {value}, use 6, 8 or 10")),
+    }
+}
+
+fn positive(value: &str) -> Result<usize, String> {
+    match value.parse() {
+        Ok(0) | Err(_) => Err(format!("{value} isn't a positive number")),
+        Ok(count) => Ok(count),
+    }
+}
+
+fn unit(value: &str) -> Result<f32, String> {
+    match value.parse() {
+        Ok(share) if (0.0..=1.0).contains(&share) => Ok(share),
+        _ => Err(format!("{value} isn't a number between 0 and 1")),
+    }
+}
+
+#[cfg(test)]
+mod cli_tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Cli, clap::Error> {
+        let args = std::iter::once("rusty_othello_ai").chain(args.iter().copied());
+        Cli::try_parse_from(with_subcommand(args.map(String::from).collect()))
+    }
+
+    #[test]
+    fn test_parse_subcommands() {
+        <Cli as clap::CommandFactory>::command().debug_assert();
+        let Command::PlayServer(args) =
+            parse(&["play-server", "--server", "http://host:1", "--color", "w"])
+                .unwrap()
+                .command
+        else {
+            panic!("Expected play-server");
+        };
+        assert_eq!(args.color, Side::White);
+        assert_eq!(args.server, "http://host:1");
+        assert_eq!(args.search.limit(100), SearchLimit::Iterations(100));
+
+        let Command::Selfplay(args) = parse(&["selfplay", "--size", "6", "--threads", "2"])
+            .unwrap()
+            .command
+        else {
+            panic!("Expected selfplay");
+        };
+        assert_eq!((args.size, args.threads, args.games), (6, 2, 1));
+
+        // The color first still plays on the server
+        let Command::PlayServer(args) =
+            parse(&["black", "--time", "60", "--poll"]).unwrap().command
+        else {
+            panic!("Expected play-server");
+        };
+        assert_eq!(args.color, Side::Black);
+        assert!(args.poll);
+        assert!(matches!(args.search.limit(100), SearchLimit::Clock(_)));
+
+        assert!(parse(&["selfplay", "--size", "7"]).is_err());
+        assert!(parse(&["bench", "--threads", "0"]).is_err());
+        assert!(parse(&["bench", "--searches", "0"]).is_err());
+        assert!(parse(&["bench", "--batch", "0"]).is_err());
+
+        let Command::Analyze(args) = parse(&["analyze", "--selection", "puct", "--rave"])
+            .unwrap()
+            .command
+        else {
+            panic!("Expected analyze");
+        };
+        assert_eq!(args.search.selection(), Selection::Puct);
+        assert!(args.search.rave);
+        assert_eq!(args.search.limit(100), SearchLimit::Iterations(100));
+        assert!(parse(&["play-server"]).is_err());
+        assert!(parse(&["b", "--network", "net.json", "--rollout-weight", "2"]).is_err());
+        assert!(parse(&["b", "--rollout-weight", "0.5"]).is_err());
+
+        // A network searches with its priors unless another policy is asked for
+        let Command::PlayServer(args) = parse(&["b", "--network", "net.json"]).unwrap().command
+        else {
+            panic!("Expected play-server");
+        };
+        assert_eq!(args.search.selection(), Selection::Puct);
+        let Command::PlayServer(args) = parse(&["b"]).unwrap().command else {
+            panic!("Expected play-server");
+        };
+        assert_eq!(args.search.selection(), Selection::Ucb1);
+        assert!(parse(&[
+            "play-server",
+            "--color",
+            "b",
+            "--iterations",
+            "5",
+            "--time",
+            "5"
+        ])
+        .is_err());
+    }
+}
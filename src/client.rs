@@ -0,0 +1,341 @@
+use crate::mcts::MCTS;
+use crate::othello::{parse_state, Action, Color, State};
+use crate::time_manager::SearchLimit;
+use std::fmt;
+use std::thread::sleep;
+use std::time::Duration;
+
+// The server used when no other is configured
+pub const DEFAULT_SERVER_URL: &str = "http://localhost:8181";
+// Environment variable that can hold the server's base URL
+pub const SERVER_URL_ENV: &str = "OTHELLO_SERVER_URL";
+// How long the server may hold a long polling request for the turn
+const LONG_POLL_TIMEOUT: Duration = Duration::from_secs(30);
+// Extra time given to a long polling request before it times out on the client
+const LONG_POLL_MARGIN: Duration = Duration::from_secs(5);
+// Delay between turn requests when the server doesn't support long polling
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClientError {
+    // The server couldn't be reached or the connection failed
+    Transport(String),
+    // The server answered with an error status code and body
+    Status(u16, String),
+    // The server answered with something that doesn't follow the protocol
+    UnexpectedResponse(String),
+}
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Transport(e) => write!(f, "Couldn't reach the server: {e}"),
+            ClientError::Status(code, body) => write!(f, "Server answered {code}: {body}"),
+            ClientError::UnexpectedResponse(body) => {
+                write!(f, "Unexpected response from server: {body}")
+            }
+        }
+    }
+}
+impl std::error::Error for ClientError {}
+impl From<ureq::Error> for ClientError {
+    fn from(e: ureq::Error) -> Self {
+        match e {
+            ureq::Error::Status(code, response) => {
+                ClientError::Status(code, response.into_string().unwrap_or_default())
+            }
+            ureq::Error::Transport(transport) => ClientError::Transport(transport.to_string()),
+        }
+    }
+}
+impl ClientError {
+    // Whether the request may succeed if it is sent again
+    fn is_retryable(&self) -> bool {
+        match self {
+            ClientError::Transport(_) => true,
+            ClientError::Status(code, _) => *code >= 500,
+            ClientError::UnexpectedResponse(_) => false,
+        }
+    }
+}
+
+// How failed requests are retried, the delay doubles after every attempt up to `max_delay`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    // Number of retries after the first attempt, None retries forever
+    pub max_retries: Option<usize>,
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+}
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: None,
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(10),
+        }
+    }
+}
+
+// The string used for a player in the server's protocol
+pub fn protocol_color(color: Color) -> &'static str {
+    match color {
+        Color::BLACK => "false",
+        Color::WHITE => "true",
+    }
+}
+
+// Client for the endpoints of the Othello game server
+#[derive(Debug, Clone)]
+pub struct GameServerClient {
+    base_url: String,
+    agent: ureq::Agent,
+    retry: RetryPolicy,
+    long_polling: bool,
+}
+
+impl GameServerClient {
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            agent: ureq::Agent::new(),
+            retry: RetryPolicy::default(),
+            long_polling: true,
+        }
+    }
+
+    // Sets the timeout for connecting to the server and for each whole request
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.agent = ureq::AgentBuilder::new()
+            .timeout_connect(timeout)
+            .timeout(timeout)
+            .build();
+        self
+    }
+
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    // Whether `play` waits for its turn with long polling, servers without support for it
+    // fall back to polling the turn
+    pub fn with_long_polling(mut self, long_polling: bool) -> Self {
+        self.long_polling = long_polling;
+        self
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    // Checks whether it is the given player's turn
+    pub fn is_my_turn(&self, color: Color) -> Result<bool, ClientError> {
+        let body = self.with_retries(|| self.get("/turn")?.into_string().map_err(transport))?;
+        parse_turn(color, body)
+    }
+
+    // Waits until it is the given player's turn, answering false if the timeout passes first
+    // This uses the long polling endpoint of this crate's server, other servers answer
+    // with an error status
+    pub fn wait_for_turn(&self, color: Color, timeout: Duration) -> Result<bool, ClientError> {
+        let url = format!(
+            "{}/waitTurn/{}?timeout={}",
+            self.base_url,
+            protocol_color(color),
+            timeout.as_secs_f64()
+        );
+        let body = self.with_retries(|| {
+            self.agent
+                .get(&url)
+                .timeout(timeout + LONG_POLL_MARGIN)
+                .call()?
+                .into_string()
+                .map_err(transport)
+        })?;
+        parse_turn(color, body)
+    }
+
+    // Fetches the current board and turn from the server
+    pub fn get_state(&self) -> Result<State, ClientError> {
+        let json: serde_json::Value =
+            self.with_retries(|| self.get("/board")?.into_json().map_err(transport))?;
+        parse_state(&json).map_err(|e| ClientError::UnexpectedResponse(e.to_string()))
+    }
+
+    // Plays the action for the given player, or skips their turn if there is no action
+    pub fn send_move(&self, color: Color, action: Option<&Action>) -> Result<(), ClientError> {
+        let path = match action {
+            Some(action) => format!(
+                "/setChoice/{}/{}/{}",
+                action.position.x,
+                action.position.y,
+                protocol_color(color)
+            ),
+            None => format!("/skipTurn/{}", protocol_color(color)),
+        };
+        self.with_retries(|| self.get(&path).map(|_| ()))
+    }
+
+    // Reports the progress of the AI's search, this is only sent once and never retried
+    pub fn send_status(
+        &self,
+        current: usize,
+        total: usize,
+        color: Color,
+    ) -> Result<(), ClientError> {
+        let url = format!(
+            "{}/AIStatus/{}/{}/{}",
+            self.base_url,
+            current,
+            total,
+            protocol_color(color)
+        );
+        self.agent.post(&url).call()?;
+        Ok(())
+    }
+
+    // Plays as the given color until the game is over and returns the final state
+    // The AI ponders on the last known state while waiting for its turn
+    pub fn play(&self, color: Color, mcts: &mut MCTS, start: State, limit: SearchLimit) -> State {
+        let mut state = start;
+        let mut long_polling = self.long_polling;
+        let mut limit = limit;
+        let send_progress = |current: usize, total: usize, color: &Color| {
+            _ = self.send_status(current, total, *color);
+        };
+
+        // The main game loop
+        loop {
+            // The AI checks if it's its turn, if so, it gets the current game state and performs a search using MCTS
+            let (my_turn, _) = mcts.ponder_while(state, || match long_polling {
+                true => self.wait_for_turn(color, LONG_POLL_TIMEOUT),
+                false => {
+                    let turn = self.is_my_turn(color);
+                    if let Ok(false) = turn {
+                        sleep(POLL_INTERVAL);
+                    }
+                    turn
+                }
+            });
+            match my_turn {
+                Ok(true) => {
+                    state = match self.get_state() {
+                        Ok(state) => state,
+                        Err(e) => {
+                            eprintln!("Error fetching the board: {}", e);
+                            sleep(Duration::from_secs(1));
+                            continue;
+                        }
+                    };
+                    if state.is_game_over() {
+                        return state;
+                    }
+                    let choice = limit.search(mcts, state, send_progress);
+
+                    // If a valid action is found, it sends the move to the server and updates the game state
+                    if let Ok(action) = choice {
+                        if let Err(e) = self.send_move(color, Some(&action)) {
+                            eprintln!("Error sending move: {}", e);
+                        }
+                        state = state.do_action(Some(action));
+                    }
+                    // If no valid action is found, it sends a pass move to the server and updates the game state
+                    else {
+                        if let Err(e) = self.send_move(color, None) {
+                            eprintln!("Error skipping turn: {}", e);
+                        }
+                        state = state.do_action(None);
+                    }
+                    // The server keeps the turn with the opponent once our move ends the game
+                    if state.is_game_over() {
+                        return state;
+                    }
+                }
+                // If it's not the AI's turn it has pondered while waiting and checks again
+                Ok(false) => {}
+                Err(ClientError::Status(code, _)) if long_polling => {
+                    eprintln!("Long polling failed with status {code}, falling back to polling");
+                    long_polling = false;
+                }
+                Err(e) => {
+                    eprintln!("Error checking turn: {}", e);
+                    sleep(Duration::from_secs(1));
+                }
+            }
+        }
+    }
+
+    fn get(&self, path: &str) -> Result<ureq::Response, ClientError> {
+        let url = format!("{}{}", self.base_url, path);
+        Ok(self.agent.get(&url).call()?)
+    }
+
+    // Runs the request until it succeeds, fails with an error that can't be retried
+    // or runs out of retries
+    fn with_retries<T>(
+        &self,
+        request: impl Fn() -> Result<T, ClientError>,
+    ) -> Result<T, ClientError> {
+        let mut delay = self.retry.initial_delay;
+        let mut retries = 0;
+        loop {
+            match request() {
+                Err(e)
+                    if e.is_retryable()
+                        && self.retry.max_retries.is_none_or(|max| retries < max) =>
+                {
+                    eprintln!("Request failed: {e}, will retry after {:?}", delay);
+                    sleep(delay);
+                    delay = std::cmp::min(delay.saturating_mul(2), self.retry.max_delay);
+                    retries += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+// Reads the answer to a turn request, true if it is the given player's turn
+fn parse_turn(color: Color, body: String) -> Result<bool, ClientError> {
+    match body.trim() {
+        x if x == protocol_color(color) => Ok(true),
+        x if x == protocol_color(color.opponent()) => Ok(false),
+        _ => Err(ClientError::UnexpectedResponse(body)),
+    }
+}
+
+fn transport(e: std::io::Error) -> ClientError {
+    ClientError::Transport(e.to_string())
+}
+
+#[cfg(test)]
+mod client_tests {
+    use super::*;
+
+    #[test]
+    fn test_protocol_color() {
+        assert_eq!(protocol_color(Color::BLACK), "false");
+        assert_eq!(protocol_color(Color::WHITE), "true");
+    }
+    #[test]
+    fn test_unreachable_server_gives_up() {
+        // Bind and drop a listener to get a port nothing listens on
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let client = GameServerClient::new(&format!("http://127.0.0.1:{port}/"))
+            .with_timeout(Duration::from_secs(1))
+            .with_retry_policy(RetryPolicy {
+                max_retries: Some(2),
+                initial_delay: Duration::ZERO,
+                max_delay: Duration::ZERO,
+            });
+        assert_eq!(client.base_url(), format!("http://127.0.0.1:{port}"));
+        assert!(matches!(
+            client.is_my_turn(Color::BLACK),
+            Err(ClientError::Transport(_))
+        ));
+    }
+}
@@ -1,40 +1,273 @@
-use std::io::Write;
-use std::isize;
+use std::io::{IsTerminal, Write};
 use std::process::exit;
 
-use crate::mcts::MCTS;
-use crate::othello::{caculate_win, print_state, Action, Color, Position, State};
+use rusty_othello_ai::mcts::{Proof, SearchReport, MCTS};
+use rusty_othello_ai::othello::{caculate_win, print_state, Action, Color, Position, State};
+use rusty_othello_ai::render::{render_state, RenderOptions};
+
+use crate::cli::ConsoleArgs;
+
+// Number of iterations used when analysing positions for the player
+const ANALYSIS_ITERATIONS: usize = 10_000;
+// Number of moves listed by 'analyze' when no count is given
+const DEFAULT_ANALYSIS_MOVES: usize = 5;
 
 enum GameCommand {
-    SKIP,
-    QUIT,
-    INVALID,
-    MOVE(usize, usize),
+    Skip,
+    Quit,
+    Invalid,
+    Move(usize, usize),
+    Hint,
+    Analyze(usize),
+    Moves,
+    Eval,
 }
 
-pub fn console_game() {
-    let mut win_balance: isize = 0;
-    let a = 1.0;
-    println!("Game mode: player vs AI\n");
-    let mut state = State::new();
-    let mut mcts = MCTS::new("true", a);
-    _ = std::io::stdout().flush();
-    let mut ai_iterations = 20000;
-    loop {
-        print_state(state);
-        state = player_turn(state.clone());
-        if state.remaining_moves == 0 {
-            break;
+// Who controls the two sides of a console game
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum GameMode {
+    #[value(name = "player-ai", alias = "pva", alias = "1")]
+    PlayerVsAi,
+    #[value(name = "ai-ai", alias = "ava", alias = "2")]
+    AiVsAi,
+    #[value(name = "player-player", alias = "pvp", alias = "3")]
+    PlayerVsPlayer,
+}
+
+// AI strength, mapped to the number of search iterations used for the first move
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+    Expert,
+}
+impl Difficulty {
+    fn iterations(&self) -> usize {
+        match self {
+            Difficulty::Easy => 1_000,
+            Difficulty::Medium => 5_000,
+            Difficulty::Hard => 20_000,
+            Difficulty::Expert => 100_000,
         }
-        print_state(state);
-        state = ai_turn(&mut mcts, state.clone(), ai_iterations);
-        ai_iterations += ai_iterations / 100;
+    }
+}
 
-        if state.remaining_moves == 0 {
-            break;
+#[derive(Debug, Clone)]
+pub struct ConsoleOptions {
+    pub mode: GameMode,
+    // The player's color in player vs AI games
+    pub player_color: Color,
+    pub difficulty: Difficulty,
+    pub exploration: f32,
+    // Seeds the AI's random moves, the white AI uses the next seed
+    pub seed: Option<u64>,
+    pub start: State,
+    // Play in the full screen terminal UI instead of the line based console
+    pub tui: bool,
+}
+impl Default for ConsoleOptions {
+    fn default() -> Self {
+        Self {
+            mode: GameMode::PlayerVsAi,
+            player_color: Color::BLACK,
+            difficulty: Difficulty::Hard,
+            exploration: 1.0,
+            seed: None,
+            start: State::new(),
+            tui: false,
         }
     }
-    //print_state(state);
+}
+impl ConsoleOptions {
+    // Reads the console options from the arguments of the console command
+    // "--setup" asks for the mode, color, difficulty and exploration interactively
+    pub fn from_args(args: &ConsoleArgs) -> Result<ConsoleOptions, String> {
+        let mut options = ConsoleOptions {
+            mode: args.mode,
+            player_color: args.color.into(),
+            difficulty: args.level,
+            exploration: args.explore,
+            seed: args.seed,
+            start: args.position.state()?,
+            tui: args.tui,
+        };
+        if args.setup {
+            options.interactive_setup();
+        }
+        Ok(options)
+    }
+
+    fn interactive_setup(&mut self) {
+        loop {
+            match parse_mode(&prompt(
+                "Game mode: player vs AI (1), AI vs AI (2) or player vs player (3) [1]: ",
+                "1",
+            )) {
+                Ok(mode) => {
+                    self.mode = mode;
+                    break;
+                }
+                Err(e) => println!("{e}"),
+            }
+        }
+        if self.mode == GameMode::PlayerVsAi {
+            loop {
+                match parse_color(&prompt("Play as black or white [black]: ", "black")) {
+                    Ok(color) => {
+                        self.player_color = color;
+                        break;
+                    }
+                    Err(e) => println!("{e}"),
+                }
+            }
+        }
+        if self.mode != GameMode::PlayerVsPlayer {
+            loop {
+                match parse_difficulty(&prompt(
+                    "AI level: easy, medium, hard or expert [hard]: ",
+                    "hard",
+                )) {
+                    Ok(difficulty) => {
+                        self.difficulty = difficulty;
+                        break;
+                    }
+                    Err(e) => println!("{e}"),
+                }
+            }
+            loop {
+                match prompt("Exploration constant [1.0]: ", "1.0").parse() {
+                    Ok(exploration) => {
+                        self.exploration = exploration;
+                        break;
+                    }
+                    Err(_) => println!("Please enter a number"),
+                }
+            }
+        }
+    }
+}
+
+fn parse_mode(mode: &str) -> Result<GameMode, String> {
+    match mode {
+        "1" | "player-ai" | "pva" => Ok(GameMode::PlayerVsAi),
+        "2" | "ai-ai" | "ava" => Ok(GameMode::AiVsAi),
+        "3" | "player-player" | "pvp" => Ok(GameMode::PlayerVsPlayer),
+        _ => Err(format!(
+            "Unknown game mode {mode}, use player-ai, ai-ai or player-player"
+        )),
+    }
+}
+
+fn parse_color(color: &str) -> Result<Color, String> {
+    match color {
+        "black" | "b" => Ok(Color::BLACK),
+        "white" | "w" => Ok(Color::WHITE),
+        _ => Err(format!("Unknown color {color}, use black or white")),
+    }
+}
+
+fn parse_difficulty(level: &str) -> Result<Difficulty, String> {
+    match level {
+        "easy" => Ok(Difficulty::Easy),
+        "medium" => Ok(Difficulty::Medium),
+        "hard" => Ok(Difficulty::Hard),
+        "expert" => Ok(Difficulty::Expert),
+        _ => Err(format!(
+            "Unknown level {level}, use easy, medium, hard or expert"
+        )),
+    }
+}
+
+// Prints the question and reads the answer, returning the default for empty answers
+fn prompt(question: &str, default: &str) -> String {
+    print!("{question}");
+    let _ = std::io::stdout().flush();
+    let mut buf = String::new();
+    let _ = std::io::stdin().read_line(&mut buf);
+    match buf.trim().to_lowercase() {
+        answer if answer.is_empty() => default.to_string(),
+        answer => answer,
+    }
+}
+
+// A side in a console game, AI players keep their own search tree and iteration count
+pub enum Player {
+    Human,
+    Ai(Box<MCTS>, usize),
+}
+impl Player {
+    fn ai(color: Color, options: &ConsoleOptions) -> Player {
+        let seed = match color {
+            Color::BLACK => options.seed,
+            Color::WHITE => options.seed.map(|seed| seed.wrapping_add(1)),
+        };
+        let mcts = MCTS::new(color, options.exploration);
+        let mcts = match seed {
+            Some(seed) => mcts.with_seed(seed),
+            None => mcts,
+        };
+        Player::Ai(Box::new(mcts), options.difficulty.iterations())
+    }
+    // Creates the black and white players for the configured game mode
+    pub fn for_options(options: &ConsoleOptions) -> (Player, Player) {
+        match options.mode {
+            GameMode::PlayerVsAi if options.player_color == Color::BLACK => {
+                (Player::Human, Player::ai(Color::WHITE, options))
+            }
+            GameMode::PlayerVsAi => (Player::ai(Color::BLACK, options), Player::Human),
+            GameMode::AiVsAi => (
+                Player::ai(Color::BLACK, options),
+                Player::ai(Color::WHITE, options),
+            ),
+            GameMode::PlayerVsPlayer => (Player::Human, Player::Human),
+        }
+    }
+    // The search tree that can ponder while the other side is a player thinking about their move
+    pub fn ponderer(&mut self) -> Option<&mut MCTS> {
+        match self {
+            Player::Ai(mcts, _) => Some(mcts),
+            Player::Human => None,
+        }
+    }
+}
+
+pub fn console_game(options: ConsoleOptions) {
+    let mut win_balance: isize = 0;
+    let (mut black, mut white) = Player::for_options(&options);
+    match (&black, &white) {
+        (Player::Human, Player::Human) => println!("Game mode: player vs player\n"),
+        (Player::Human, _) => println!("Game mode: player vs AI\n"),
+        (_, Player::Human) => println!("Game mode: AI vs player\n"),
+        (_, _) => println!("Game mode: AI vs AI\n"),
+    }
+    let mut state = options.start;
+    // Separate tree searching from the player's side for hints and analysis
+    let mut analysis = MCTS::new(Color::BLACK, options.exploration);
+    let mut render_options = RenderOptions::default();
+    _ = std::io::stdout().flush();
+    while state.remaining_moves > 0 {
+        render_options.legal_moves = true;
+        render_options.score = true;
+        render_options.colors = std::io::stdout().is_terminal();
+        println!("{}", render_state(&state, &render_options));
+        let previous = state;
+        let (player, opponent) = match state.next_turn {
+            Color::BLACK => (&mut black, &mut white),
+            Color::WHITE => (&mut white, &mut black),
+        };
+        state = match player {
+            Player::Human => player_turn(state, &mut analysis, opponent.ponderer()),
+            Player::Ai(mcts, ai_iterations) => {
+                let new_state = ai_turn(mcts, state, *ai_iterations);
+                *ai_iterations += *ai_iterations / 100;
+                new_state
+            }
+        };
+        render_options = RenderOptions::for_move(&previous, &state);
+    }
+    render_options.score = true;
+    println!("{}", render_state(&state, &render_options));
     win_balance += match caculate_win(state) {
         Some(Color::WHITE) => {
             println!("White wins!");
@@ -54,76 +287,166 @@ pub fn console_game() {
 }
 
 fn ai_turn(mcts: &mut MCTS, state: State, iterations: usize) -> State {
-    let dev_null = |_a: usize, _b: usize, _c: &Color| -> () { /*println!("Progress: {a}/{b}")*/ };
-    let action = mcts.search(state.clone(), iterations, dev_null);
-    if action.is_ok() {
-        println!("{:?}", action.clone().unwrap().position);
-        state.clone().do_action(Some(action.unwrap().clone()))
+    let dev_null = |_a: usize, _b: usize, _c: &Color| { /*println!("Progress: {a}/{b}")*/ };
+    let action = mcts.search(state, iterations, dev_null);
+    if let Ok(action) = action {
+        println!("{:?}", action.position);
+        state.do_action(Some(action))
     } else {
-        state.clone().do_action(None)
+        state.do_action(None)
     }
 }
 
-fn player_turn(state: State) -> State {
-    let mut player_choice;
+// The AI opponent, if any, ponders while the player enters commands
+fn player_turn(state: State, analysis: &mut MCTS, mut opponent: Option<&mut MCTS>) -> State {
     loop {
         print!("Enter coordinates for desired move: ");
         let _ = std::io::stdout().flush();
-        let cmd = read_command();
+        let cmd = match &mut opponent {
+            Some(mcts) => mcts.ponder_while(state, read_command).0,
+            None => read_command(),
+        };
         match cmd {
-            GameCommand::QUIT => exit(0),
-            GameCommand::INVALID => {
-                println!("Please provide a valid command 'quit' 'skip' or 'x,y'")
+            GameCommand::Quit => exit(0),
+            GameCommand::Invalid => {
+                println!("Please provide a valid command 'quit' 'skip' 'hint' 'analyze [n]' 'moves' 'eval' or 'y,x'")
             }
-            GameCommand::SKIP => {
-                player_choice = None;
-                break;
+            GameCommand::Hint => {
+                let report = analyze(analysis, state);
+                match report.moves.first() {
+                    Some(best) => println!(
+                        "Hint: {} (estimated win rate {:.1}%)",
+                        format_action(best.action.as_ref()),
+                        best.win_rate() * 100.0
+                    ),
+                    None => println!("No moves available, you have to skip"),
+                }
             }
-            GameCommand::MOVE(x_index, y_index) => {
-                player_choice = Some(Action {
-                    color: Color::BLACK,
-                    position: Position {
-                        x: x_index,
-                        y: y_index,
-                    },
-                });
-                if state
-                    .get_actions()
-                    .contains(&player_choice.clone().unwrap())
-                {
-                    break;
-                } else {
-                    println!("Invalid move.");
-                    let pos: Vec<(usize, usize)> = state
-                        .get_actions()
-                        .iter()
-                        .map(|a| (a.position.y, a.position.x))
-                        .collect();
-                    println!("Valid moves: {:?}", pos);
-                    print_state(state);
+            GameCommand::Analyze(count) => {
+                print_report(&analyze(analysis, state), count);
+            }
+            GameCommand::Moves => print_moves(state),
+            GameCommand::Eval => {
+                for color in [Color::BLACK, Color::WHITE] {
+                    let mut side = state;
+                    side.next_turn = color;
+                    println!(
+                        "{:?}: {} discs, {} moves",
+                        color,
+                        state.count_discs(color),
+                        side.get_actions().len()
+                    );
+                }
+                let report = analyze(analysis, state);
+                if let Some(best) = report.moves.first() {
+                    println!(
+                        "Estimated win rate for {:?}: {:.1}%",
+                        state.next_turn,
+                        best.win_rate() * 100.0
+                    );
+                }
+            }
+            GameCommand::Skip if state.get_actions().is_empty() => return state.do_action(None),
+            GameCommand::Skip => {
+                println!("You can only skip without valid moves");
+                print_moves(state);
+            }
+            GameCommand::Move(x_index, y_index) => {
+                let player_choice = Action::new(state.next_turn, Position::new(x_index, y_index));
+                match state.try_action(&player_choice) {
+                    Ok(new_state) => return new_state,
+                    Err(e) => {
+                        println!("Invalid move: {e}");
+                        print_moves(state);
+                        print_state(state);
+                    }
                 }
             }
         }
     }
-    state.clone().do_action(player_choice)
+}
+
+// Searches the state from the player's side and reports the statistics of the moves
+fn analyze(analysis: &mut MCTS, state: State) -> SearchReport {
+    let dev_null = |_a: usize, _b: usize, _c: &Color| {};
+    analysis
+        .search_with_report(state, ANALYSIS_ITERATIONS, dev_null)
+        .1
+}
+
+// Prints the statistics of the first moves of the report and the expected line of play
+pub fn print_report(report: &SearchReport, count: usize) {
+    println!("Move   Visits  Win rate     UCB  Proven");
+    for stats in report.moves.iter().take(count) {
+        let proof = match stats.proof {
+            Some(Proof::Win) => "win",
+            Some(Proof::Draw) => "draw",
+            Some(Proof::Loss) => "loss",
+            None => "",
+        };
+        println!(
+            "{:<6} {:>6} {:>8.1}% {:>7.3}  {proof}",
+            format_action(stats.action.as_ref()),
+            stats.visits,
+            stats.win_rate() * 100.0,
+            stats.ucb
+        );
+    }
+    let line: Vec<String> = report
+        .principal_variation
+        .iter()
+        .take(10)
+        .map(|action| format_action(action.as_ref()))
+        .collect();
+    println!("Expected line: {}", line.join(" "));
+}
+
+fn print_moves(state: State) {
+    let moves: Vec<String> = state
+        .get_actions()
+        .iter()
+        .map(|action| format_action(Some(action)))
+        .collect();
+    if moves.is_empty() {
+        println!("No valid moves, you have to skip");
+    } else {
+        println!("Valid moves: {}", moves.join(" "));
+    }
+}
+
+// Formats an action in the same "y,x" form used to enter moves
+pub fn format_action(action: Option<&Action>) -> String {
+    match action {
+        Some(action) => format!("{},{}", action.position.y, action.position.x),
+        None => "skip".to_string(),
+    }
 }
 
 fn read_command() -> GameCommand {
     let mut buf = String::new();
     let _ = std::io::stdin().read_line(&mut buf);
     match buf.to_lowercase().as_str().trim() {
-        "quit" => GameCommand::QUIT,
-        "skip" => GameCommand::SKIP,
+        "quit" => GameCommand::Quit,
+        "skip" => GameCommand::Skip,
+        "hint" => GameCommand::Hint,
+        "moves" => GameCommand::Moves,
+        "eval" => GameCommand::Eval,
+        line if line.starts_with("analyze") => match line.trim_start_matches("analyze").trim() {
+            "" => GameCommand::Analyze(DEFAULT_ANALYSIS_MOVES),
+            count => count
+                .parse()
+                .map_or(GameCommand::Invalid, GameCommand::Analyze),
+        },
         line => {
-            let cmd: Vec<&str> = line.trim().split(",").clone().collect();
-            match (cmd.get(0), cmd.get(1)) {
+            let cmd: Vec<&str> = line.trim().split(',').collect();
+            match (cmd.first(), cmd.get(1)) {
                 (Some(cmd_1), Some(cmd_2)) => {
                     match (cmd_1.parse::<usize>(), cmd_2.parse::<usize>()) {
-                        (Ok(y_index), Ok(x_index)) => GameCommand::MOVE(x_index, y_index),
-                        _ => GameCommand::INVALID,
+                        (Ok(y_index), Ok(x_index)) => GameCommand::Move(x_index, y_index),
+                        _ => GameCommand::Invalid,
                     }
                 }
-                _ => GameCommand::INVALID,
+                _ => GameCommand::Invalid,
             }
         }
     }
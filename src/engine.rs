@@ -0,0 +1,517 @@
+use crate::mcts::MCTS;
+use crate::othello::{caculate_win, Action, Color, Position, State, BOARD_SIZE};
+use crate::render::{render_state, RenderOptions};
+use crate::time_manager::{SearchLimit, TimeManager};
+use std::io::{BufRead, Write};
+use std::time::Duration;
+
+// Iterations for the first move when neither iterations nor a clock are given
+pub const DEFAULT_ENGINE_ITERATIONS: usize = 10_000;
+
+const COMMANDS: [&str; 18] = [
+    "boardsize",
+    "clear_board",
+    "final_score",
+    "genmove",
+    "known_command",
+    "list_commands",
+    "name",
+    "play",
+    "ponder",
+    "protocol_version",
+    "quit",
+    "set_iterations",
+    "setboard",
+    "showboard",
+    "time_left",
+    "time_settings",
+    "undo",
+    "version",
+];
+
+// Plays games driven by a GTP style text protocol, one command per line
+// Every command may start with a numeric ID, which is repeated in its response
+// Successful responses start with '=', failures with '?', and both end with an empty line
+// Moves are written as a column letter and a row number, e.g. "d3", or "pass"
+//
+// Besides the usual GTP commands the engine knows
+// "setboard <position>" to set up a position in the encoding of `State`'s `FromStr`,
+// "ponder on|off" to search while waiting for the next command and
+// "set_iterations <n>" to search a fixed number of iterations per move
+// "time_settings <main> <byo yomi> <stones>" starts a clock, the byo yomi time per stone is
+// used as the increment
+// A side without legal moves passes implicitly when the other side is asked to play
+pub struct Engine {
+    state: State,
+    history: Vec<State>,
+    mcts: MCTS,
+    // Builds the search tree of every new game for the given color
+    new_tree: Box<dyn Fn(Color) -> Result<MCTS, String>>,
+    // The limit given to the engine, used without time settings
+    default_limit: SearchLimit,
+    // The limit every game starts with, replaced by the time settings
+    limit: SearchLimit,
+    // The limits of black's and white's searches, every side has its own clock
+    limits: [SearchLimit; 2],
+    ponder: bool,
+    quit: bool,
+}
+
+impl Engine {
+    pub fn new(
+        limit: SearchLimit,
+        new_tree: impl Fn(Color) -> Result<MCTS, String> + 'static,
+    ) -> Result<Engine, String> {
+        Ok(Engine {
+            state: State::new(),
+            history: Vec::new(),
+            // A black tree scores the nodes correctly for either side to move
+            mcts: new_tree(Color::BLACK)?,
+            new_tree: Box::new(new_tree),
+            default_limit: limit,
+            limit,
+            limits: [limit; 2],
+            ponder: false,
+            quit: false,
+        })
+    }
+
+    // Answers commands from the input until it ends or the engine is told to quit
+    pub fn run(&mut self, mut input: impl BufRead, mut output: impl Write) -> std::io::Result<()> {
+        while !self.quit {
+            let mut line = String::new();
+            let read = match self.ponder {
+                true => {
+                    self.mcts
+                        .ponder_while(self.state, || input.read_line(&mut line))
+                        .0
+                }
+                false => input.read_line(&mut line),
+            };
+            if read? == 0 {
+                break;
+            }
+            if let Some(response) = self.respond(&line) {
+                write!(output, "{response}")?;
+                output.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    // Executes a command line and returns the formatted response,
+    // None for empty lines and comments
+    pub fn respond(&mut self, line: &str) -> Option<String> {
+        let line = line.split('#').next().unwrap_or_default();
+        let mut words = line.split_whitespace().peekable();
+        let id = words
+            .peek()
+            .filter(|word| word.chars().all(|c| c.is_ascii_digit()))
+            .map(|id| id.to_string());
+        if id.is_some() {
+            words.next();
+        }
+        let name = words.next()?;
+        let args: Vec<&str> = words.collect();
+        let (status, text) = match self.command(name, &args) {
+            Ok(text) => ('=', text),
+            Err(text) => ('?', text),
+        };
+        let id = id.unwrap_or_default();
+        Some(match text.is_empty() {
+            true => format!("{status}{id}\n\n"),
+            false => format!("{status}{id} {text}\n\n"),
+        })
+    }
+
+    fn command(&mut self, name: &str, args: &[&str]) -> Result<String, String> {
+        let arg = |i: usize| args.get(i).copied().ok_or("missing argument".to_string());
+        match name {
+            "protocol_version" => Ok("2".to_string()),
+            "name" => Ok("Rusty Othello AI".to_string()),
+            "version" => Ok(env!("CARGO_PKG_VERSION").to_string()),
+            "known_command" => Ok(COMMANDS.contains(&arg(0)?).to_string()),
+            "list_commands" => Ok(COMMANDS.join("\n")),
+            "quit" => {
+                self.quit = true;
+                Ok(String::new())
+            }
+            "boardsize" => match arg(0)?.parse() {
+                Ok(BOARD_SIZE) => Ok(String::new()),
+                _ => Err("unacceptable size".to_string()),
+            },
+            "clear_board" => {
+                self.set_state(State::new())?;
+                Ok(String::new())
+            }
+            "setboard" => {
+                let state = args.join("").parse().map_err(|e| format!("{e}"))?;
+                self.set_state(state)?;
+                Ok(String::new())
+            }
+            "play" => {
+                let color = parse_color(arg(0)?)?;
+                self.take_turn(color)?;
+                let new_state = match parse_move(arg(1)?)? {
+                    Some(position) => self
+                        .state
+                        .try_action(&Action::new(color, position))
+                        .map_err(|e| format!("illegal move: {e}"))?,
+                    None if self.state.get_actions().is_empty() => self.state.do_action(None),
+                    None => return Err("illegal move: there are legal moves".to_string()),
+                };
+                self.history.push(self.state);
+                self.state = new_state;
+                Ok(String::new())
+            }
+            "genmove" => {
+                let color = parse_color(arg(0)?)?;
+                if self.state.is_game_over() {
+                    return Err("the game is over".to_string());
+                }
+                self.take_turn(color)?;
+                let dev_null = |_a: usize, _b: usize, _c: &Color| {};
+                let limit = &mut self.limits[color_index(color)];
+                let action = limit.search(&mut self.mcts, self.state, dev_null).ok();
+                if action.is_none() && !self.state.get_actions().is_empty() {
+                    return Err("no move found".to_string());
+                }
+                let response = format_move(action.as_ref().map(|a| a.position));
+                self.history.push(self.state);
+                self.state = self.state.do_action(action);
+                Ok(response)
+            }
+            "undo" => {
+                self.state = self.history.pop().ok_or("cannot undo")?;
+                Ok(String::new())
+            }
+            "showboard" => {
+                let options = RenderOptions {
+                    legal_moves: true,
+                    score: true,
+                    ..RenderOptions::default()
+                };
+                Ok(format!("\n{}", render_state(&self.state, &options)))
+            }
+            "final_score" => {
+                let black = self.state.count_discs(Color::BLACK);
+                let white = self.state.count_discs(Color::WHITE);
+                Ok(match caculate_win(self.state) {
+                    Some(Color::BLACK) => format!("B+{}", black - white),
+                    Some(Color::WHITE) => format!("W+{}", white - black),
+                    None => "0".to_string(),
+                })
+            }
+            "time_settings" => {
+                let main_time = parse_seconds(arg(0)?)?;
+                let byo_yomi = parse_seconds(arg(1)?)?;
+                let stones: u32 = arg(2)?.parse().map_err(|_| "invalid stones")?;
+                let per_stone = byo_yomi / stones.max(1);
+                let limit = match (main_time, byo_yomi) {
+                    // No time limit
+                    (Duration::ZERO, Duration::ZERO) => self.default_limit,
+                    // The byo yomi time of the first move is on the clock from the start,
+                    // so the engine can search without main time
+                    _ => SearchLimit::Clock(TimeManager::new(main_time + per_stone, per_stone)),
+                };
+                self.limit = limit;
+                self.limits = [limit; 2];
+                Ok(String::new())
+            }
+            "time_left" => {
+                let color = parse_color(arg(0)?)?;
+                let remaining = parse_seconds(arg(1)?)?;
+                if let SearchLimit::Clock(clock) = &mut self.limits[color_index(color)] {
+                    clock.set_remaining(remaining);
+                }
+                Ok(String::new())
+            }
+            "ponder" => {
+                self.ponder = match arg(0)? {
+                    "on" => true,
+                    "off" => false,
+                    _ => return Err("expected on or off".to_string()),
+                };
+                Ok(String::new())
+            }
+            "set_iterations" => {
+                let iterations = arg(0)?.parse().map_err(|_| "invalid iterations")?;
+                self.limit = SearchLimit::Iterations(iterations);
+                self.limits = [self.limit; 2];
+                Ok(String::new())
+            }
+            _ => Err("unknown command".to_string()),
+        }
+    }
+
+    // Makes it the color's turn, passing for the other side if it has no legal moves
+    fn take_turn(&mut self, color: Color) -> Result<(), String> {
+        if color != self.state.next_turn {
+            if !self.state.get_actions().is_empty() || self.state.is_game_over() {
+                return Err(format!("it is not {}'s turn", color_name(color)));
+            }
+            self.history.push(self.state);
+            self.state = self.state.do_action(None);
+        }
+        Ok(())
+    }
+
+    // Starts a new game from the state with a new search tree and restarted clocks
+    fn set_state(&mut self, state: State) -> Result<(), String> {
+        self.mcts = (self.new_tree)(Color::BLACK)?;
+        self.state = state;
+        self.history.clear();
+        self.limits = [self.limit; 2];
+        Ok(())
+    }
+}
+
+fn parse_color(color: &str) -> Result<Color, String> {
+    match color.to_lowercase().as_str() {
+        "b" | "black" => Ok(Color::BLACK),
+        "w" | "white" => Ok(Color::WHITE),
+        _ => Err(format!("invalid color {color}")),
+    }
+}
+
+fn color_index(color: Color) -> usize {
+    match color {
+        Color::BLACK => 0,
+        Color::WHITE => 1,
+    }
+}
+
+fn color_name(color: Color) -> &'static str {
+    match color {
+        Color::BLACK => "black",
+        Color::WHITE => "white",
+    }
+}
+
+// Reads a move like "d3", with the column as a letter and the row counted from 1
+// None is a pass
+fn parse_move(text: &str) -> Result<Option<Position>, String> {
+    let text = text.to_lowercase();
+    if text == "pass" {
+        return Ok(None);
+    }
+    let invalid = || format!("invalid move {text}");
+    let mut chars = text.chars();
+    let column = chars.next().ok_or_else(invalid)?;
+    let row: usize = chars.as_str().parse().map_err(|_| invalid())?;
+    let x = (column as usize).wrapping_sub('a' as usize);
+    if x >= BOARD_SIZE || row == 0 || row > BOARD_SIZE {
+        return Err(invalid());
+    }
+    Ok(Some(Position::new(x, row - 1)))
+}
+
+fn format_move(position: Option<Position>) -> String {
+    match position {
+        Some(pos) => format!("{}{}", (b'a' + pos.x as u8) as char, pos.y + 1),
+        None => "pass".to_string(),
+    }
+}
+
+fn parse_seconds(seconds: &str) -> Result<Duration, String> {
+    seconds
+        .parse()
+        .ok()
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+        .ok_or(format!("invalid time {seconds}"))
+}
+
+#[cfg(test)]
+mod engine_tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    fn engine() -> Engine {
+        Engine::new(SearchLimit::Iterations(100), |color| {
+            Ok(MCTS::new(color, 1.0))
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_move_notation() {
+        assert_eq!(parse_move("d3"), Ok(Some(Position::new(3, 2))));
+        assert_eq!(parse_move("H8"), Ok(Some(Position::new(7, 7))));
+        assert_eq!(parse_move("pass"), Ok(None));
+        assert!(parse_move("i1").is_err());
+        assert!(parse_move("a0").is_err());
+        assert!(parse_move("a9").is_err());
+        assert!(parse_move("3d").is_err());
+        assert_eq!(format_move(Some(Position::new(3, 2))), "d3");
+        assert_eq!(format_move(None), "pass");
+    }
+    #[test]
+    fn test_responses() {
+        let mut engine = engine();
+        assert_eq!(
+            engine.respond("1 protocol_version"),
+            Some("=1 2\n\n".to_string())
+        );
+        assert_eq!(engine.respond("boardsize 8"), Some("=\n\n".to_string()));
+        assert_eq!(
+            engine.respond("2 boardsize 10"),
+            Some("?2 unacceptable size\n\n".to_string())
+        );
+        assert_eq!(
+            engine.respond("known_command genmove"),
+            Some("= true\n\n".to_string())
+        );
+        assert_eq!(engine.respond("  # comment"), None);
+        assert_eq!(
+            engine.respond("fly"),
+            Some("? unknown command\n\n".to_string())
+        );
+    }
+    #[test]
+    fn test_play_genmove_undo() {
+        let mut engine = engine();
+        assert_eq!(engine.respond("play b d3"), Some("=\n\n".to_string()));
+        assert!(engine
+            .respond("play b c4")
+            .unwrap()
+            .starts_with("? it is not black's turn"));
+        assert!(engine
+            .respond("play w a1")
+            .unwrap()
+            .starts_with("? illegal move"));
+        assert!(engine
+            .respond("play w pass")
+            .unwrap()
+            .starts_with("? illegal move"));
+
+        let response = engine.respond("genmove w").unwrap();
+        let text = response.trim().trim_start_matches("= ");
+        let position = parse_move(text).unwrap().unwrap();
+        assert_eq!(engine.state.disc_at(position), Some(Color::WHITE));
+        assert_eq!(engine.state.next_turn, Color::BLACK);
+
+        assert_eq!(engine.respond("undo"), Some("=\n\n".to_string()));
+        assert_eq!(engine.state.disc_at(position), None);
+        assert_eq!(engine.respond("undo"), Some("=\n\n".to_string()));
+        assert_eq!(engine.state, State::new());
+        assert_eq!(
+            engine.respond("undo"),
+            Some("? cannot undo\n\n".to_string())
+        );
+    }
+    #[test]
+    fn test_setboard_and_score() {
+        let mut engine = engine();
+        let position = format!("{}{} X", "X".repeat(40), "O".repeat(24));
+        assert_eq!(
+            engine.respond(&format!("setboard {position}")),
+            Some("=\n\n".to_string())
+        );
+        assert_eq!(
+            engine.respond("final_score"),
+            Some("= B+16\n\n".to_string())
+        );
+        assert_eq!(
+            engine.respond("genmove b"),
+            Some("? the game is over\n\n".to_string())
+        );
+        assert!(engine.respond("setboard XO").unwrap().starts_with('?'));
+    }
+    #[test]
+    fn test_run() {
+        let mut engine = engine();
+        let input = "time_settings 1 0 0\nponder on\ngenmove black\nshowboard\nquit\nname\n";
+        let mut output = Vec::new();
+        engine.run(input.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let responses: Vec<&str> = output.split("\n\n").collect();
+        // The command after quit isn't answered
+        assert_eq!(responses.len(), 6, "{output}");
+        assert!(responses[2].starts_with("= "));
+        assert!(responses[3].contains("Next: White"));
+        assert!(matches!(engine.limits[0], SearchLimit::Clock(_)));
+    }
+    #[test]
+    fn test_byo_yomi_and_time_left() {
+        let mut engine = engine();
+        assert_eq!(
+            engine.respond("time_settings 0 1 1"),
+            Some("=\n\n".to_string())
+        );
+        // Without main time the engine still plays a legal move
+        let response = engine.respond("genmove b").unwrap();
+        let text = response.trim().trim_start_matches("= ");
+        let position = parse_move(text).unwrap().unwrap();
+        assert!(engine.history[0]
+            .get_actions()
+            .iter()
+            .any(|a| a.position == position));
+
+        // The clock of the named color is set, not that of the side to move
+        assert_eq!(
+            engine.respond("time_left b 30 0"),
+            Some("=\n\n".to_string())
+        );
+        let remaining = |limit: &SearchLimit| match limit {
+            SearchLimit::Clock(clock) => clock.remaining(),
+            SearchLimit::Iterations(_) => Duration::ZERO,
+        };
+        assert_eq!(remaining(&engine.limits[0]), Duration::from_secs(30));
+        assert_eq!(remaining(&engine.limits[1]), Duration::from_secs(1));
+    }
+    #[test]
+    fn test_new_game() {
+        let trees = Rc::new(Cell::new(0));
+        let built = trees.clone();
+        let mut engine = Engine::new(SearchLimit::Iterations(100), move |color| {
+            built.set(built.get() + 1);
+            Ok(MCTS::new(color, 1.0))
+        })
+        .unwrap();
+        engine.respond("time_settings 10 0 0");
+        engine.respond("genmove b");
+        engine.respond("time_left b 3 0");
+        // A new game gets a new tree and restarts the clocks with the time settings
+        assert_eq!(engine.respond("clear_board"), Some("=\n\n".to_string()));
+        assert_eq!(trees.get(), 2);
+        match &engine.limits[0] {
+            SearchLimit::Clock(clock) => assert_eq!(clock.remaining(), Duration::from_secs(10)),
+            SearchLimit::Iterations(_) => panic!("the clock was dropped"),
+        }
+        // Without time settings the engine returns to the limit it started with
+        engine.respond("time_settings 0 0 0");
+        assert!(matches!(engine.limits[1], SearchLimit::Iterations(100)));
+
+        // The game isn't reset when its tree can't be built
+        engine.respond("play b d3");
+        engine.new_tree = Box::new(|_| Err("no tree".to_string()));
+        assert_eq!(
+            engine.respond("clear_board"),
+            Some("? no tree\n\n".to_string())
+        );
+        assert_eq!(engine.history.len(), 1);
+    }
+    #[test]
+    fn test_implicit_pass() {
+        // White is to move without a legal move, black can play d1
+        let mut engine = engine();
+        let position = format!("XXO{} O", "-".repeat(61));
+        engine.respond(&format!("setboard {position}"));
+        assert_eq!(engine.respond("genmove b"), Some("= d1\n\n".to_string()));
+        assert_eq!(engine.state.count_discs(Color::BLACK), 4);
+
+        engine.respond(&format!("setboard {position}"));
+        assert_eq!(engine.respond("play b d1"), Some("=\n\n".to_string()));
+        // Undo takes back the move and then the pass
+        engine.respond("undo");
+        assert_eq!(engine.state.next_turn, Color::BLACK);
+        // A side with legal moves doesn't pass
+        assert!(engine
+            .respond("play w d1")
+            .unwrap()
+            .starts_with("? it is not white's turn"));
+        engine.respond("undo");
+        assert_eq!(engine.state.next_turn, Color::WHITE);
+    }
+}
@@ -0,0 +1,287 @@
+use crate::othello::{simulate_game_with, Action, Color, State};
+use crate::selection::MovePrior;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+// The value of a leaf, summed over the games or estimates behind it
+// Results are seen from white's side, 1 if white won and -1 if black won
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct LeafValue {
+    // Number of results, counted as visits of the leaf
+    pub visits: usize,
+    pub white_score: f32,
+    // Sum of the squared results, for the variance used by UCB1-Tuned
+    pub white_squares: f32,
+}
+impl LeafValue {
+    // A single result, e.g. of one simulated game or one estimate
+    pub fn single(white_result: f32) -> LeafValue {
+        LeafValue {
+            visits: 1,
+            white_score: white_result,
+            white_squares: white_result * white_result,
+        }
+    }
+
+    pub fn add(&mut self, other: LeafValue) {
+        self.visits += other.visits;
+        self.white_score += other.white_score;
+        self.white_squares += other.white_squares;
+    }
+}
+
+// Evaluates leaves of the search tree in batches, instead of a single simulated game per leaf
+// Evaluators working on many states at once, like neural networks, get all leaves in one call
+pub trait LeafEvaluator<const N: usize>: Send {
+    // Returns the value of every leaf, in the same order
+    fn evaluate(&mut self, leaves: &[State<N>]) -> Vec<LeafValue>;
+
+    // Like `evaluate`, and returns the priors of every leaf's fields, row by row, if the evaluator
+    // has them, so the search doesn't evaluate a leaf again to weigh its moves
+    fn evaluate_with_priors(
+        &mut self,
+        leaves: &[State<N>],
+    ) -> (Vec<LeafValue>, Option<Vec<Vec<f32>>>) {
+        (self.evaluate(leaves), None)
+    }
+}
+
+// The estimate of an evaluator for a state
+#[derive(Debug, Clone, PartialEq)]
+pub struct Evaluation {
+    // Expected result in [-1, 1] for the player to move
+    pub value: f32,
+    // Prior weight of playing on every field, row by row, the search only uses the legal moves
+    pub priors: Vec<f32>,
+}
+
+// Estimates the value of a state and priors for its moves, like the networks of AlphaZero
+pub trait Evaluator<const N: usize>: Send + Sync {
+    fn evaluate(&self, state: &State<N>) -> Evaluation;
+}
+
+// Evaluates the leaves of the search with an evaluator, mixing in random games by their weight
+// A weight of 0 only uses the evaluator and a weight of 1 only the random games
+pub struct EvaluatorLeaves<const N: usize, E> {
+    evaluator: Arc<E>,
+    rollout_weight: f32,
+    rng: StdRng,
+}
+impl<const N: usize, E: Evaluator<N>> EvaluatorLeaves<N, E> {
+    pub fn new(evaluator: Arc<E>, rollout_weight: f32, seed: u64) -> EvaluatorLeaves<N, E> {
+        EvaluatorLeaves {
+            evaluator,
+            rollout_weight,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+impl<const N: usize, E: Evaluator<N>> LeafEvaluator<N> for EvaluatorLeaves<N, E> {
+    fn evaluate(&mut self, leaves: &[State<N>]) -> Vec<LeafValue> {
+        self.evaluate_with_priors(leaves).0
+    }
+
+    // Every leaf is evaluated once for its value and priors
+    fn evaluate_with_priors(
+        &mut self,
+        leaves: &[State<N>],
+    ) -> (Vec<LeafValue>, Option<Vec<Vec<f32>>>) {
+        let mut values = Vec::with_capacity(leaves.len());
+        let mut priors = Vec::with_capacity(leaves.len());
+        for leaf in leaves {
+            let evaluation = self.evaluator.evaluate(leaf);
+            let mut white_result = match self.rollout_weight < 1.0 {
+                true => to_move_value(leaf, evaluation.value),
+                false => 0.0,
+            };
+            if self.rollout_weight > 0.0 {
+                let rollout = simulate_game_with(leaf, &mut self.rng) as f32;
+                white_result += self.rollout_weight * (rollout - white_result);
+            }
+            values.push(LeafValue::single(white_result));
+            priors.push(evaluation.priors);
+        }
+        (values, Some(priors))
+    }
+}
+
+// Weighs the moves with the priors of an evaluator
+// The search only needs it for states that weren't evaluated as leaves, like the root
+pub struct EvaluatorPriors<E>(pub Arc<E>);
+impl<const N: usize, E: Evaluator<N>> MovePrior<N> for EvaluatorPriors<E> {
+    fn priors(&self, state: &State<N>, actions: &[Action]) -> Vec<f32> {
+        action_priors::<N>(&self.0.evaluate(state).priors, actions)
+    }
+}
+
+// Picks the priors of the moves from the priors of all fields, given row by row
+pub fn action_priors<const N: usize>(field_priors: &[f32], actions: &[Action]) -> Vec<f32> {
+    actions
+        .iter()
+        .map(|action| field_priors[action.position.y * N + action.position.x])
+        .collect()
+}
+
+// Turns a value for the player to move into one for white, or the other way around
+fn to_move_value<const N: usize>(state: &State<N>, value: f32) -> f32 {
+    match state.next_turn {
+        Color::WHITE => value,
+        Color::BLACK => -value,
+    }
+}
+
+// A share of the games simulated for a leaf by one worker
+struct PlayoutJob<const N: usize> {
+    leaf: usize,
+    state: State<N>,
+    games: usize,
+}
+
+// Simulates a number of random games for every leaf on a pool of worker threads
+// and sums up their results
+pub struct ParallelPlayouts<const N: usize> {
+    playouts: usize,
+    jobs: Vec<Sender<PlayoutJob<N>>>,
+    results: Receiver<(usize, LeafValue)>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl<const N: usize> ParallelPlayouts<N> {
+    // Starts the threads, every worker draws its random moves from its own generator seeded by `seed`
+    pub fn new(playouts: usize, threads: usize, seed: u64) -> ParallelPlayouts<N> {
+        let (result_sender, results) = channel();
+        let mut jobs = Vec::new();
+        let mut workers = Vec::new();
+        for worker in 0..threads.max(1) {
+            let (job_sender, job_receiver) = channel::<PlayoutJob<N>>();
+            let result_sender = result_sender.clone();
+            let mut rng = StdRng::seed_from_u64(seed.wrapping_add(worker as u64));
+            workers.push(std::thread::spawn(move || {
+                for job in job_receiver {
+                    let mut value = LeafValue::default();
+                    for _ in 0..job.games {
+                        value.add(LeafValue::single(
+                            simulate_game_with(&job.state, &mut rng) as f32
+                        ));
+                    }
+                    if result_sender.send((job.leaf, value)).is_err() {
+                        return;
+                    }
+                }
+            }));
+            jobs.push(job_sender);
+        }
+        ParallelPlayouts {
+            playouts: playouts.max(1),
+            jobs,
+            results,
+            workers,
+        }
+    }
+}
+
+impl<const N: usize> LeafEvaluator<N> for ParallelPlayouts<N> {
+    // The games of every leaf are split evenly over the workers
+    fn evaluate(&mut self, leaves: &[State<N>]) -> Vec<LeafValue> {
+        let workers = self.jobs.len();
+        let mut pending = 0;
+        for (leaf, state) in leaves.iter().enumerate() {
+            for (worker, jobs) in self.jobs.iter().enumerate() {
+                let games = self.playouts / workers + usize::from(worker < self.playouts % workers);
+                if games > 0 {
+                    let job = PlayoutJob {
+                        leaf,
+                        state: *state,
+                        games,
+                    };
+                    jobs.send(job).expect("Playout workers run until dropped");
+                    pending += 1;
+                }
+            }
+        }
+        let mut values = vec![LeafValue::default(); leaves.len()];
+        for _ in 0..pending {
+            let (leaf, value) = self
+                .results
+                .recv()
+                .expect("Playout workers run until dropped");
+            values[leaf].add(value);
+        }
+        values
+    }
+}
+
+impl<const N: usize> Drop for ParallelPlayouts<N> {
+    fn drop(&mut self) {
+        // Closing the job channels ends the workers
+        self.jobs.clear();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod evaluation_tests {
+    use super::*;
+
+    #[test]
+    fn test_evaluator_adapters() {
+        // Values every state as won for the player to move and prefers the first row
+        struct Optimist;
+        impl Evaluator<4> for Optimist {
+            fn evaluate(&self, _state: &State<4>) -> Evaluation {
+                let mut priors = vec![1.0; 16];
+                priors[..4].fill(4.0);
+                Evaluation { value: 1.0, priors }
+            }
+        }
+        let white_to_move: State<4> = "OO-O OXOX XO-O -OXO O".parse().unwrap();
+        let black_to_move = white_to_move.do_action(white_to_move.get_actions().first().cloned());
+        let optimist = Arc::new(Optimist);
+        let mut leaves = EvaluatorLeaves::new(optimist.clone(), 0.0, 1);
+        let values = leaves.evaluate(&[white_to_move, black_to_move]);
+        assert_eq!(
+            values,
+            vec![LeafValue::single(1.0), LeafValue::single(-1.0)]
+        );
+        // Only random games
+        let mut leaves = EvaluatorLeaves::new(optimist.clone(), 1.0, 1);
+        assert_eq!(leaves.evaluate(&[white_to_move])[0].white_score.abs(), 1.0);
+
+        let actions = white_to_move.get_actions();
+        let priors = EvaluatorPriors(optimist).priors(&white_to_move, &actions);
+        let expected: Vec<f32> = actions
+            .iter()
+            .map(|a| if a.position.y == 0 { 4.0 } else { 1.0 })
+            .collect();
+        assert_eq!(priors, expected);
+    }
+    #[test]
+    fn test_parallel_playouts() {
+        let mut playouts: ParallelPlayouts<8> = ParallelPlayouts::new(10, 3, 1);
+        let start: State = State::new();
+        let finished: State = "XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX"
+            .parse()
+            .unwrap();
+        let values = playouts.evaluate(&[start, finished]);
+        assert_eq!(values[0].visits, 10);
+        assert!(values[0].white_score.abs() <= 10.0);
+        // Every game of a finished board has the same result
+        assert_eq!(
+            values[1],
+            LeafValue {
+                visits: 10,
+                white_score: -10.0,
+                white_squares: 10.0,
+            }
+        );
+
+        // The workers draw the same games from the same seed
+        let mut again: ParallelPlayouts<8> = ParallelPlayouts::new(10, 3, 1);
+        assert_eq!(again.evaluate(&[start])[0], values[0]);
+    }
+}
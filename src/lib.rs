@@ -1,2 +1,12 @@
+pub mod client;
+pub mod engine;
+pub mod evaluation;
 pub mod mcts;
+pub mod mlp;
 pub mod othello;
+pub mod render;
+pub mod selection;
+pub mod selfplay;
+pub mod server;
+pub mod time_manager;
+pub mod training;
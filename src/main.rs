@@ -1,172 +1,166 @@
+use std::io::IsTerminal;
 use std::process::exit;
-use std::time::Duration;
-use std::usize;
-use std::{borrow::Borrow, thread::sleep};
-use ureq::Response;
+use std::time::Instant;
+mod cli;
 mod console_game;
-mod mcts;
-mod othello;
-use console_game::console_game;
-use mcts::MCTS;
-use othello::{parse_state, Action, Color, State};
+mod tui;
+use cli::{
+    AnalyzeArgs, BenchArgs, Cli, Command, EngineArgs, PlayServerArgs, SelfplayArgs, ServeArgs,
+};
+use console_game::{console_game, print_report, ConsoleOptions};
+use rusty_othello_ai::engine::{Engine, DEFAULT_ENGINE_ITERATIONS};
+use rusty_othello_ai::mcts::MCTS;
+use rusty_othello_ai::othello::{Color, State};
+use rusty_othello_ai::render::{render_state, RenderOptions};
+use rusty_othello_ai::selfplay::selfplay;
+use rusty_othello_ai::server::{GameServer, DEFAULT_SESSION};
+use tui::tui_game;
 
-const SERVER_URL: &str = "http://localhost:8181";
+// Number of iterations for the first move of a server game when neither iterations nor a clock are given
+const SERVER_ITERATIONS: usize = 10_000;
+// Iterations of an analysis or of every benchmarked search when neither iterations nor a clock are given
+const ANALYZE_ITERATIONS: usize = 10_000;
+const BENCH_ITERATIONS: usize = 10_000;
 
 fn main() {
-    // Get command line arguments and determine the AI's color
-    // The AI color is determined based on the first argument passed to the program
-    // If the argument is not recognized, the program will panic
-    let args: Vec<String> = std::env::args().collect();
-    let ai_color;
-    match args
-        .get(1)
-        .expect("Please specify color to the AI")
-        .to_lowercase()
-    {
-        x if x == "false" => ai_color = x,
-        x if x == "0" => ai_color = "false".to_string(),
-        x if x == "b" => ai_color = "false".to_string(),
-        x if x == "black" => ai_color = "false".to_string(),
-        x if x == "true" => ai_color = x,
-        x if x == "1" => ai_color = "true".to_string(),
-        x if x == "w" => ai_color = "true".to_string(),
-        x if x == "white" => ai_color = "true".to_string(),
-        x if x == "console" => {
-            console_game();
-            exit(0);
-        }
-        _ => panic!("Please pass a proper argument to the AI"),
-    }
-    // Initialize the game state and the Monte Carlo Tree Search (MCTS)
-    // The MCTS is initialized with a new node that represents the current game state
-    let mut state = State::new();
-    let mut mcts = MCTS::new(&ai_color, 1.0);
-    let mut choice: Result<Action, ()>;
-    let mut ai_iterations: usize = 10_000;
-
-    // The main game loop
-    loop {
-        // The AI checks if it's its turn, if so, it gets the current game state and performs a search using MCTS
-        match is_my_turn(ai_color.borrow()) {
-            Ok(true) => {
-                state = get_game_state();
-                choice = mcts.search(state, ai_iterations, send_progress);
-                // Gives the ai 2% more iterations every round to balance the game simulations
-                // being shorter
-                ai_iterations += ai_iterations / 50;
-
-                // If a valid action is found, it sends the move to the server and updates the game state
-                if choice.is_ok() {
-                    let _ = send_move(&ai_color, Some(choice.clone().unwrap()));
-                    state.do_action(Some(choice.unwrap()));
-                }
-                // If no valid action is found, it sends a pass move to the server and updates the game state
-                else {
-                    let _ = send_move(&ai_color, None);
-                    state.do_action(None);
-                }
-            }
-            // If it's not the AI's turn, it performs a search using MCTS and waits
-            Ok(false) => {
-                let dev_null = |_a: usize, _b: usize, _c: &Color| -> () {};
-                _ = mcts.search(state, 1000, dev_null);
-                //sleep(Duration::from_secs(1));
-            }
-            Err(e) => {
-                eprintln!("Error checking turn: {}", e);
-                sleep(Duration::from_secs(1));
+    let result = match Cli::from_env().command {
+        Command::PlayServer(args) => play_server(&args),
+        Command::Console(args) => ConsoleOptions::from_args(&args).map(|options| {
+            if options.tui {
+                tui_game(options);
+            } else {
+                console_game(options);
             }
-        }
-    }
-}
-// Function to check if it's the AI's turn
-// This function makes a GET request to the server and parses the response
-fn is_my_turn(ai: &String) -> Result<bool, Box<dyn std::error::Error>> {
-    let mut delay = Duration::from_secs(1);
-    let opponent = match ai {
-        x if x == "true" => "false",
-        _ => "true",
+        }),
+        Command::Selfplay(args) => play_selfplay(&args),
+        Command::Analyze(args) => analyze(&args),
+        Command::Bench(args) => bench(&args),
+        Command::Serve(args) => serve(&args),
+        Command::Engine(args) => engine(args),
     };
-    loop {
-        let url = format!("{}/turn", SERVER_URL);
-        match ureq::get(&url).call() {
-            Ok(response) => {
-                let body = response.into_string()?;
-                match body.trim() {
-                    // If the response is "true", it's the AI's turn and the function returns Ok(true)
-                    x if x == ai => return Ok(true),
-                    // If the response is "false", it's not the AI's turn and the function returns Ok(false)
-                    x if x == opponent => return Ok(false),
-                    // If the response is anything else, the function returns an error
-                    _ => return Err("Unexpected response from server".into()),
-                }
-            }
-            Err(e) => {
-                // Error occurred, possibly a network issue or server error, wait before trying again
-                eprintln!(
-                    "Error checking turn: {}, will retry after {:?} seconds",
-                    e, delay
-                );
-                sleep(delay);
-                delay = std::cmp::min(delay.saturating_mul(2), Duration::from_secs(10));
-            }
-        }
+    if let Err(e) = result {
+        eprintln!("{e}");
+        exit(1);
     }
 }
 
-// Function to get the current game state
-// This function makes a GET request to the server to get the current game state
-// The game state is parsed from the response and returned
-fn get_game_state() -> State {
-    let mut delay = Duration::from_secs(3);
-    loop {
-        match get_json() {
-            Ok(resp) => {
-                return parse_state(resp.into_json().expect("Error parsing response to json"))
-            }
-            Err(_e) => {
-                sleep(delay);
-                delay *= 2;
-                delay = std::cmp::min(Duration::from_millis(10000), delay);
-            }
-        }
+// Plays one side of a game on the server
+// Until the first board is fetched from the server the AI ponders on the starting state
+fn play_server(args: &PlayServerArgs) -> Result<(), String> {
+    let state = args.position.state()?;
+    let color = args.color.into();
+    let mut mcts = args.search.mcts(color)?;
+    let limit = args.search.limit(SERVER_ITERATIONS);
+    args.client().play(color, &mut mcts, state, limit);
+    Ok(())
+}
+
+// Lets two AIs play each other and prints every result and the total
+fn play_selfplay(args: &SelfplayArgs) -> Result<(), String> {
+    let results = selfplay(&args.config(), |game, winner| {
+        let result = match winner {
+            Some(Color::BLACK) => "black wins",
+            Some(Color::WHITE) => "white wins",
+            None => "draw",
+        };
+        println!("Game {}: {result}", game + 1);
+    })?;
+    if results.skipped > 0 {
+        println!("Skipped {} games already written", results.skipped);
     }
+    println!(
+        "Black wins: {}, white wins: {}, draws: {}",
+        results.black_wins, results.white_wins, results.draws
+    );
+    println!("Result: {}", results.win_balance());
+    Ok(())
 }
 
-// This function makes a GET request to the server to get the current game board
-// The response is returned as a Result
-fn get_json() -> Result<Response, ureq::Error> {
-    let url = format!("{}/board", SERVER_URL);
-    let resp = ureq::get(&url).call()?;
-    Ok(resp)
+// Searches the position from the side to move and prints the statistics of its best moves
+fn analyze(args: &AnalyzeArgs) -> Result<(), String> {
+    let state: State = args.position.state()?;
+    let mut mcts = args.search.mcts(Color::BLACK)?;
+    let mut limit = args.search.limit(ANALYZE_ITERATIONS);
+    let _ = limit.search(&mut mcts, state, |_, _, _| {});
+    let report = mcts
+        .report(&state)
+        .expect("The searched state is added to the tree");
+    let render_options = RenderOptions {
+        score: true,
+        colors: std::io::stdout().is_terminal(),
+        ..RenderOptions::default()
+    };
+    println!("{}", render_state(&state, &render_options));
+    println!(
+        "{:?} to move, {} visits",
+        state.next_turn, report.root_visits
+    );
+    print_report(&report, args.moves);
+    Ok(())
 }
 
-// Function to send the AI's move to the server
-fn send_move(player: &String, ai_move: Option<Action>) -> Result<Response, ureq::Error> {
-    let resp;
-    let url;
-    // If the AI has a move, format the URL for the setChoice endpoint
-    // The setChoice endpoint requires the x and y coordinates of the move and the player
-    if ai_move.is_some() {
-        let ai_choice = ai_move.unwrap();
-        url = format!(
-            "{}/setChoice/{}/{}/{}",
-            SERVER_URL, ai_choice.position.x, ai_choice.position.y, player
-        );
+// Runs a search from the position on every thread and prints the combined speed
+fn bench(args: &BenchArgs) -> Result<(), String> {
+    if args.search.time.is_some() {
+        return Err("bench searches a number of iterations, --time isn't supported".to_string());
     }
-    // If the AI does not have a move, format the URL for the skipTurn endpoint
-    // The skipTurn endpoint requires the player
-    else {
-        url = format!("{}/skipTurn/{}", SERVER_URL, player);
+    let state: State = args.position.state()?;
+    let iterations = args.search.iterations.unwrap_or(BENCH_ITERATIONS);
+    // Every search gets its own seed, so they don't repeat each other's games
+    let mut searches = Vec::with_capacity(args.searches);
+    for search in 0..args.searches {
+        let mut search_args = args.search.clone();
+        search_args.seed = args
+            .search
+            .seed
+            .map(|seed| seed.wrapping_add(search as u64));
+        searches.push(search_args.mcts(Color::BLACK)?);
     }
-    resp = ureq::get(&url).call()?;
-    Ok(resp)
+    let start = Instant::now();
+    let playouts: usize = std::thread::scope(|scope| {
+        let searches: Vec<_> = searches
+            .into_iter()
+            .map(|mut mcts: MCTS| {
+                scope.spawn(move || {
+                    let (_, report) = mcts.search_with_report(state, iterations, |_, _, _| {});
+                    report.root_visits
+                })
+            })
+            .collect();
+        searches
+            .into_iter()
+            .map(|search| search.join().expect("The search doesn't panic"))
+            .sum()
+    });
+    let elapsed = start.elapsed();
+    let iterations = iterations * args.searches;
+    println!(
+        "{iterations} iterations with {playouts} games in {} searches in {:.3}s, {:.0} iterations and {:.0} games per second",
+        args.searches,
+        elapsed.as_secs_f64(),
+        iterations as f64 / elapsed.as_secs_f64(),
+        playouts as f64 / elapsed.as_secs_f64()
+    );
+    Ok(())
 }
-fn send_progress(current: usize, total: usize, ai_color: &Color) {
-    let color = match ai_color {
-        Color::BLACK => "false",
-        Color::WHITE => "true",
-    };
-    let url = format!("{}/AIStatus/{}/{}/{}", SERVER_URL, current, total, color);
-    _ = ureq::post(&url).call();
+
+// Hosts games for clients of the game server protocol until the process is killed
+fn serve(args: &ServeArgs) -> Result<(), String> {
+    let state = args.position.state()?;
+    let server = GameServer::start(&args.addr)
+        .map_err(|e| format!("Couldn't listen on {}: {e}", args.addr))?;
+    server.open_session(DEFAULT_SESSION, state, args.opponent.map(Into::into));
+    println!("Serving games on {}", server.url());
+    server.wait();
+    Ok(())
+}
+
+// Plays through the text engine protocol on stdin and stdout
+// Every new game gets a new tree built from the search options
+fn engine(args: EngineArgs) -> Result<(), String> {
+    let limit = args.search.limit(DEFAULT_ENGINE_ITERATIONS);
+    let mut engine = Engine::new(limit, move |color| args.search.mcts(color))?;
+    engine
+        .run(std::io::stdin().lock(), std::io::stdout())
+        .map_err(|e| format!("Engine error: {e}"))
 }
@@ -1,110 +1,573 @@
-use crate::othello::{simulate_game, Action, Color, State};
-use rand::Rng;
+use crate::evaluation::{
+    action_priors, Evaluator, EvaluatorLeaves, EvaluatorPriors, LeafEvaluator, LeafValue,
+    ParallelPlayouts,
+};
+use crate::othello::{
+    caculate_win, simulate_game_recording, simulate_game_with, Action, Color, State, BOARD_SIZE,
+};
+use crate::selection::{normalize, ChildStats, MovePrior, SelectionPolicy, UniformPrior};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+// The proven result of the game from a node, for the player who made the move leading to it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Proof {
+    Win,
+    Draw,
+    Loss,
+}
+impl Proof {
+    // The same result for the other player
+    pub fn opposite(&self) -> Proof {
+        match self {
+            Proof::Win => Proof::Loss,
+            Proof::Draw => Proof::Draw,
+            Proof::Loss => Proof::Win,
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
-pub struct Node {
-    state: State,
+pub struct Node<const N: usize> {
+    state: State<N>,
     action: Option<Action>,
     untried_actions: Vec<Action>,
     visits: usize,
-    score: isize,
+    score: f32,
+    score_squares: f32,
+    // Share of the parent's prior given to the move leading here
+    prior: f32,
+    // Normalized priors of the untried actions, in the same order, once the policy needs them
+    priors: Vec<f32>,
+    // All-moves-as-first results of the move leading here, when the search keeps them
+    rave_visits: usize,
+    rave_score: f32,
+    // Set once the result of the game from here is known, for terminal states and for nodes
+    // whose result follows from their children
+    proof: Option<Proof>,
+    // Leaves below waiting for their evaluation in the current batch, each counts as a lost game
+    // for the selection so the batch spreads over different leaves
+    virtual_losses: usize,
 }
 
-impl Node {
-    pub fn new(state: State, action: Option<Action>, untried_actions: Vec<Action>) -> Node {
+impl<const N: usize> Node<N> {
+    pub fn new(state: State<N>, action: Option<Action>, untried_actions: Vec<Action>) -> Node<N> {
         Node {
             state,
             action,
             untried_actions,
             visits: 0,
-            score: 0,
+            score: 0.0,
+            score_squares: 0.0,
+            prior: 1.0,
+            priors: Vec::new(),
+            rave_visits: 0,
+            rave_score: 0.0,
+            proof: None,
+            virtual_losses: 0,
         }
     }
 
-    pub fn update_node(&mut self, result: (Color, isize)) {
-        self.visits += 1;
-        if result.0 == self.state.next_turn {
-            self.score += result.1;
-        } else {
-            self.score -= result.1
+    // Adds the results of the simulated games or evaluations of a leaf below
+    // The score is kept from the perspective of the player who made the move leading here,
+    // the opponent of the player to move, so it is the same whichever side the search is for
+    pub fn update_node(&mut self, value: &LeafValue) {
+        self.visits += value.visits;
+        self.score_squares += value.white_squares;
+        self.score += self.mover_result(value.white_score);
+    }
+
+    // Adds the results of games below the parent in which the move leading here was played
+    pub fn update_rave(&mut self, value: &LeafValue) {
+        self.rave_visits += value.visits;
+        self.rave_score += self.mover_result(value.white_score);
+    }
+
+    // The result of a proven node as a single result from white's side
+    fn proven_value(&self, proof: Proof) -> LeafValue {
+        let mover_result = match proof {
+            Proof::Win => 1.0,
+            Proof::Draw => 0.0,
+            Proof::Loss => -1.0,
+        };
+        LeafValue::single(self.mover_result(mover_result))
+    }
+
+    fn mover_result(&self, white_result: f32) -> f32 {
+        match self.state.next_turn {
+            Color::BLACK => white_result,
+            Color::WHITE => -white_result,
+        }
+    }
+
+    // Visits including the virtual losses, as seen by the selection
+    fn selection_visits(&self) -> usize {
+        self.visits + self.virtual_losses
+    }
+
+    // The statistics used by the selection, RAVE results are blended in with the given equivalence
+    fn stats(&self, rave_equivalence: Option<f32>) -> ChildStats {
+        let virtual_losses = self.virtual_losses as f32;
+        ChildStats {
+            visits: self.selection_visits(),
+            score: self.score - virtual_losses,
+            score_squares: self.score_squares + virtual_losses,
+            prior: self.prior,
+            rave_visits: self.rave_visits,
+            rave_score: self.rave_score,
+            rave_weight: rave_equivalence.map_or(0.0, |equivalence| {
+                ChildStats::rave_weight(self.visits, equivalence)
+            }),
         }
     }
-    // Calculates and returns the Upper Confidence Bound (UCB) for the Node
-    fn calculate_ucb(&self, total_count: usize, explore: f32) -> f32 {
-        (self.score as f32 / self.visits as f32)
-            + explore * (2.0 * (total_count as f32).ln() / self.visits as f32).sqrt()
+}
+
+// Statistics gathered by a search for one of the moves available from the searched state
+#[derive(Debug, Clone, PartialEq)]
+pub struct MoveStats {
+    // The move, None if the player has to pass
+    pub action: Option<Action>,
+    pub visits: usize,
+    // Mean playout result in [-1, 1] as seen by the selection, from the perspective of the player making the move
+    pub mean_value: f32,
+    // Score given to the move by the selection policy at the time of the report
+    pub ucb: f32,
+    // The result of the game after the move if the search has proven it
+    pub proof: Option<Proof>,
+}
+impl MoveStats {
+    // The mean value mapped to an estimated win rate in [0, 1]
+    pub fn win_rate(&self) -> f32 {
+        (self.mean_value + 1.0) / 2.0
+    }
+}
+
+// Summary of the search tree below a state, explaining why a move was chosen
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchReport {
+    pub root_visits: usize,
+    // Statistics for every expanded move in the order the search prefers them, proven wins
+    // first, proven losses last and the others by visits
+    pub moves: Vec<MoveStats>,
+    // The expected line of play found by following the moves the search would choose
+    pub principal_variation: Vec<Option<Action>>,
+}
+
+// The state of a running search, passed to its `StopCondition` after every iteration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchProgress {
+    // Iterations run by this search
+    pub iterations: usize,
+    // Visits added to the root by this search, more than the iterations when leaves are
+    // evaluated in batches or with several games
+    pub visits: usize,
+    // Visits of the root, including those of earlier searches
+    pub root_visits: usize,
+    // Visits of the most and second most visited moves from the root
+    pub best_visits: usize,
+    pub second_visits: usize,
+}
+
+// Decides when a search started with `MCTS::search_until` stops
+pub trait StopCondition {
+    fn should_stop(&mut self, progress: &SearchProgress) -> bool;
+}
+// Stops once the flag is set, e.g. by another thread
+impl StopCondition for &AtomicBool {
+    fn should_stop(&mut self, _progress: &SearchProgress) -> bool {
+        self.load(Ordering::Relaxed)
     }
 }
 
-#[derive()]
-pub struct MCTS {
+// Monte Carlo Tree Search over games on an N x N board
+pub struct MCTS<const N: usize = BOARD_SIZE> {
     pub size: usize,
     color: Color,
     expl: f32,
-    nodes: Vec<Node>,
+    nodes: Vec<Node<N>>,
     tree: Vec<Vec<usize>>,
     parents: Vec<Option<usize>>,
-    state_map: HashMap<State, usize>,
+    state_map: HashMap<State<N>, usize>,
+    // Picks the expanded moves and plays the simulated games
+    rng: StdRng,
+    selection: SelectionPolicy,
+    // Weighs the moves for the policies using priors
+    prior: Box<dyn MovePrior<N>>,
+    // Keeps RAVE statistics and blends them into the selection when set
+    rave_equivalence: Option<f32>,
+    // The moves of the last simulated game, recorded for the RAVE statistics
+    playout: Vec<Action>,
+    // Evaluates the leaves instead of a single simulated game when set
+    evaluator: Option<Box<dyn LeafEvaluator<N>>>,
+    // Leaves collected in every iteration before they are evaluated
+    batch_size: usize,
 }
 
-impl MCTS {
-    pub fn new(col: &str, explore: f32) -> Self {
-        let ai_color: Color;
-        match col {
-            b if b == "false".to_string() => ai_color = Color::BLACK,
-            _ => ai_color = Color::WHITE,
-        };
+impl<const N: usize> MCTS<N> {
+    // Creates an empty tree for the AI playing the given color
+    pub fn new(color: Color, explore: f32) -> Self {
         Self {
             tree: Vec::new(),
-            color: ai_color,
-            expl: explore.clone(),
+            color,
+            expl: explore,
             parents: Vec::new(),
             state_map: HashMap::new(),
             size: 0,
             nodes: Vec::new(),
+            rng: StdRng::from_entropy(),
+            selection: SelectionPolicy::default(),
+            prior: Box::new(UniformPrior),
+            rave_equivalence: None,
+            playout: Vec::new(),
+            evaluator: None,
+            batch_size: 1,
         }
     }
 
+    // Selects the moves to search with the given policy instead of UCB1
+    pub fn with_selection(mut self, selection: SelectionPolicy) -> Self {
+        self.selection = selection;
+        self
+    }
+
+    // Weighs the moves with the given prior, used by PUCT and progressive bias
+    pub fn with_prior(mut self, prior: impl MovePrior<N> + 'static) -> Self {
+        self.prior = Box::new(prior);
+        self
+    }
+
+    // Records the moves of the simulated games and keeps all-moves-as-first (RAVE) statistics,
+    // which count a game for every move the player made in it, in the tree or the simulation
+    // The selection blends them into the mean value of a move, with a weight that halves
+    // once the move has the equivalence number of visits, see `DEFAULT_RAVE_EQUIVALENCE`
+    pub fn with_rave(mut self, equivalence: f32) -> Self {
+        self.rave_equivalence = Some(equivalence);
+        self
+    }
+
+    // Evaluates every expanded leaf with the given evaluator instead of a single simulated game
+    // RAVE statistics then only count the moves made in the tree
+    pub fn with_evaluator(mut self, evaluator: impl LeafEvaluator<N> + 'static) -> Self {
+        self.evaluator = Some(Box::new(evaluator));
+        self
+    }
+
+    // Collects up to the given number of leaves in every iteration and evaluates them in one call,
+    // so the evaluator can work on all of them at once
+    // Every collected leaf puts a virtual loss on its path, which steers the following selections
+    // of the batch to other leaves
+    pub fn with_batch_size(mut self, leaves: usize) -> Self {
+        self.batch_size = leaves.max(1);
+        self
+    }
+
+    // Simulates the given number of games for every expanded leaf, spread over a pool of threads,
+    // and backs up all of their results at once
+    // The workers' random moves are drawn from seeds of the search's generator, so call
+    // `with_seed` first to repeat searches
+    pub fn with_leaf_parallel(mut self, playouts: usize, threads: usize) -> Self {
+        let seed = self.rng.gen();
+        self.with_evaluator(ParallelPlayouts::new(playouts, threads, seed))
+    }
+
+    // Searches AlphaZero style with the value and move priors of the evaluator
+    // The leaves are valued by the evaluator blended with a simulated game by the rollout weight,
+    // 0 only trusts the evaluator, and the priors are used by PUCT and progressive bias
+    // A leaf's priors come from the same evaluation as its value, only roots are evaluated for them
+    pub fn with_state_evaluator<E: Evaluator<N> + 'static>(
+        mut self,
+        evaluator: E,
+        rollout_weight: f32,
+    ) -> Self {
+        let evaluator = Arc::new(evaluator);
+        let seed = self.rng.gen();
+        self.with_prior(EvaluatorPriors(evaluator.clone()))
+            .with_evaluator(EvaluatorLeaves::new(evaluator, rollout_weight, seed))
+    }
+
+    // The color the tree was created for, its searches work for either side to move
+    pub fn color(&self) -> Color {
+        self.color
+    }
+
+    // Seeds the random moves of the search, so equal searches choose equal moves
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = StdRng::seed_from_u64(seed);
+        self
+    }
+
     // Performs a Monte Carlo Tree Search from the given state for the given number of iterations
-    // It returns the best action found or an error if no action was found
+    // At least one iteration is run, so a move is found even without any time to search
+    // It returns the best action found or an error if the player to move has to pass
+    #[allow(clippy::result_unit_err)]
     pub fn search(
         &mut self,
-        from: State,
+        from: State<N>,
         iterations: usize,
-        send_status: fn(usize, usize, &Color),
+        send_status: impl Fn(usize, usize, &Color),
     ) -> Result<Action, ()> {
-        if let Some(root) = self.state_map.get(&from).cloned() {
-            for i in 0..iterations {
-                if i % 1000 == 0 {
-                    _ = send_status(i, iterations, &self.color);
+        let root = self.root(from);
+        for i in 0..iterations.max(1) {
+            if i % 1000 == 0 {
+                send_status(i, iterations, &from.next_turn);
+            }
+            self.iterate(root);
+        }
+        self.get_best_choice(root)
+    }
+
+    // Searches from the given state until the stop condition is met, after at least one iteration
+    // It returns the best action found, like `search`, and the number of iterations
+    #[allow(clippy::result_unit_err)]
+    pub fn search_until(
+        &mut self,
+        from: State<N>,
+        mut stop: impl StopCondition,
+    ) -> (Result<Action, ()>, usize) {
+        let root = self.root(from);
+        let start_visits = self.nodes[root].visits;
+        let mut progress = self.progress(root, 0, start_visits);
+        while progress.iterations == 0 || !stop.should_stop(&progress) {
+            self.iterate(root);
+            progress = self.progress(root, progress.iterations + 1, start_visits);
+        }
+        (self.get_best_choice(root), progress.iterations)
+    }
+
+    // Searches from the given state on a background thread while `wait` runs, e.g. while the
+    // opponent thinks about their move, and stops as soon as `wait` returns
+    // The statistics stay in the tree, so searching from the opponent's reply reuses them
+    // Returns the result of `wait` and the number of iterations searched
+    pub fn ponder_while<T>(&mut self, state: State<N>, wait: impl FnOnce() -> T) -> (T, usize) {
+        if state.is_game_over() {
+            return (wait(), 0);
+        }
+        let stop = AtomicBool::new(false);
+        std::thread::scope(|scope| {
+            let search = scope.spawn(|| self.search_until(state, &stop).1);
+            let result = wait();
+            stop.store(true, Ordering::Relaxed);
+            (result, search.join().expect("The search doesn't panic"))
+        })
+    }
+
+    // Performs a search like `search` and returns a report of the resulting tree alongside the choice
+    #[allow(clippy::result_unit_err)]
+    pub fn search_with_report(
+        &mut self,
+        from: State<N>,
+        iterations: usize,
+        send_status: impl Fn(usize, usize, &Color),
+    ) -> (Result<Action, ()>, SearchReport) {
+        let choice = self.search(from, iterations, send_status);
+        let report = self
+            .report(&from)
+            .expect("The searched state is added to the tree");
+        (choice, report)
+    }
+
+    // Reports the statistics of the moves from the given state
+    // It returns None if the state hasn't been searched
+    pub fn report(&self, from: &State<N>) -> Option<SearchReport> {
+        let root = *self.state_map.get(from)?;
+        let root_visits = self.nodes[root].visits;
+        let mut moves: Vec<MoveStats> = self.tree[root]
+            .iter()
+            .map(|&child_index| {
+                let child = &self.nodes[child_index];
+                let (mean_value, ucb) = match child.visits {
+                    0 => (0.0, f32::INFINITY),
+                    _ => (
+                        child.score / child.visits as f32,
+                        self.selection.score(
+                            &child.stats(self.rave_equivalence),
+                            root_visits,
+                            self.expl,
+                        ),
+                    ),
+                };
+                MoveStats {
+                    action: child.action.clone(),
+                    visits: child.visits,
+                    mean_value,
+                    ucb,
+                    proof: child.proof,
                 }
-                let selected_node = self.select(root);
-                let expanded_node = self.expand(selected_node);
-                let result: (Color, isize) = self.simulate(expanded_node);
-                self.backpropagate(expanded_node, result);
+            })
+            .collect();
+        moves.sort_by_key(|m| std::cmp::Reverse(choice_rank(m.proof, m.visits)));
+
+        let mut principal_variation = Vec::new();
+        let mut node_index = root;
+        while let Some(best_child) = self.best_child(node_index) {
+            principal_variation.push(self.nodes[best_child].action.clone());
+            node_index = best_child;
+        }
+        Some(SearchReport {
+            root_visits,
+            moves,
+            principal_variation,
+        })
+    }
+
+    // Returns the node of the given state, adding it as a new root if it isn't in the tree
+    fn root(&mut self, state: State<N>) -> usize {
+        match self.state_map.get(&state) {
+            Some(&index) => index,
+            None => {
+                self.add_node(state, None, None);
+                self.size - 1
             }
-            return self.get_best_choice(root);
-        } else {
-            self.add_node(from.clone(), None, None);
-            return self.search(from, iterations, send_status);
         }
     }
 
+    fn progress(&self, root: usize, iterations: usize, start_visits: usize) -> SearchProgress {
+        let mut best_visits = 0;
+        let mut second_visits = 0;
+        for &child in &self.tree[root] {
+            let visits = self.nodes[child].visits;
+            if visits > best_visits {
+                second_visits = best_visits;
+                best_visits = visits;
+            } else if visits > second_visits {
+                second_visits = visits;
+            }
+        }
+        SearchProgress {
+            iterations,
+            visits: self.nodes[root].visits - start_visits,
+            root_visits: self.nodes[root].visits,
+            best_visits,
+            second_visits,
+        }
+    }
+
+    // Runs one iteration of selection, expansion, simulation and backpropagation below the root
+    // With a batch size the selection and expansion are repeated, under virtual losses, for up to
+    // that many leaves before they are evaluated together
+    // Proven nodes aren't expanded or simulated, their result is backed up instead
+    fn iterate(&mut self, root: usize) {
+        let mut leaves = Vec::new();
+        for _ in 0..self.batch_size {
+            let selected_node = self.select(root);
+            let leaf = match self.nodes[selected_node].proof {
+                Some(_) => selected_node,
+                None => self.expand(selected_node),
+            };
+            match self.nodes[leaf].proof {
+                Some(proof) => {
+                    self.playout.clear();
+                    self.propagate_proof(leaf);
+                    let value = self.nodes[leaf].proven_value(proof);
+                    self.backpropagate(leaf, value);
+                }
+                None => {
+                    self.add_virtual_loss(leaf, true);
+                    leaves.push(leaf);
+                }
+            }
+        }
+        let (values, priors) = match &mut self.evaluator {
+            Some(evaluator) if !leaves.is_empty() => {
+                let states: Vec<State<N>> =
+                    leaves.iter().map(|&leaf| self.nodes[leaf].state).collect();
+                self.playout.clear();
+                evaluator.evaluate_with_priors(&states)
+            }
+            _ => (Vec::new(), None),
+        };
+        // The priors of the evaluation are kept for the expansion of the leaves
+        if let Some(priors) = priors.filter(|_| self.selection.uses_priors()) {
+            for (&leaf, field_priors) in leaves.iter().zip(priors) {
+                let node = &mut self.nodes[leaf];
+                node.priors = action_priors::<N>(&field_priors, &node.untried_actions);
+                normalize(&mut node.priors);
+            }
+        }
+        for (i, &leaf) in leaves.iter().enumerate() {
+            self.add_virtual_loss(leaf, false);
+            let value = match values.get(i) {
+                Some(&value) => value,
+                None => self.simulate(leaf),
+            };
+            self.backpropagate(leaf, value);
+        }
+    }
+
+    // Adds a virtual loss to the nodes from the leaf up to the root, or takes it back
+    fn add_virtual_loss(&mut self, leaf: usize, add: bool) {
+        let mut node_index = Some(leaf);
+        while let Some(index) = node_index {
+            let node = &mut self.nodes[index];
+            match add {
+                true => node.virtual_losses += 1,
+                false => node.virtual_losses -= 1,
+            }
+            node_index = self.parents[index];
+        }
+    }
+
+    // Proves the ancestors of a proven node whose result follows from their children
+    fn propagate_proof(&mut self, mut node_index: usize) {
+        while let Some(parent) = self.parents[node_index] {
+            if self.nodes[parent].proof.is_some() {
+                return;
+            }
+            match self.solve(parent) {
+                Some(proof) => self.nodes[parent].proof = Some(proof),
+                None => return,
+            }
+            node_index = parent;
+        }
+    }
+
+    // The result of a node if it follows from its children, the player to move wins if one
+    // of their moves is proven to win and otherwise gets the best result once all are proven
+    fn solve(&self, node_index: usize) -> Option<Proof> {
+        let children = &self.tree[node_index];
+        let proofs = children.iter().map(|&child| self.nodes[child].proof);
+        if proofs.clone().any(|proof| proof == Some(Proof::Win)) {
+            return Some(Proof::Loss);
+        }
+        if children.is_empty() || !self.nodes[node_index].untried_actions.is_empty() {
+            return None;
+        }
+        let mut best = Proof::Loss;
+        for proof in proofs {
+            if proof? == Proof::Draw {
+                best = Proof::Draw;
+            }
+        }
+        Some(best.opposite())
+    }
+
     // Adds a new node to the MCTS with the given state, action, and parent
-    fn add_node(&mut self, state: State, action: Option<Action>, parent: Option<usize>) {
-        let new_node = Node::new(state, action, state.get_actions());
+    fn add_node(&mut self, state: State<N>, action: Option<Action>, parent: Option<usize>) {
+        let mut new_node = Node::new(state, action, state.get_actions());
+        if state.is_game_over() {
+            let mover = state.next_turn.opponent();
+            new_node.proof = Some(match caculate_win(state) {
+                Some(winner) if winner == mover => Proof::Win,
+                Some(_) => Proof::Loss,
+                None => Proof::Draw,
+            });
+        }
         self.state_map.insert(state, self.size);
         self.tree.push(Vec::new());
         self.parents.push(parent);
-        self.nodes.push(new_node.clone());
+        self.nodes.push(new_node);
         self.size += 1;
     }
 
-    // Selects a node from the MCTS using the Upper Confidence Bound (UCB) formula
+    // Selects a node from the MCTS using the score of the selection policy
+    // Proven wins are always selected and proven losses never, a proven node ends the selection
+    // Without priors a node's moves are all expanded before its children are searched, with them
+    // the most likely untried move competes with the children and ends the selection if it wins
     fn select(&self, root_index: usize) -> usize {
-        let mut max_ucb = std::f32::MIN;
-        let mut max_index = 0 as usize;
+        let mut max_index;
         let mut node_index = root_index;
         let mut depth = 0;
         loop {
@@ -116,13 +579,30 @@ impl MCTS {
             if children.is_empty() {
                 return node_index;
             }
-            if !self.nodes[node_index].untried_actions.is_empty() {
+            let node = &self.nodes[node_index];
+            if node.proof.is_some() {
                 return node_index;
             }
-            let parent_visits = self.nodes[node_index].visits;
+            let parent_visits = node.selection_visits();
+            let mut max_ucb = match node.priors.iter().copied().reduce(f32::max) {
+                _ if node.untried_actions.is_empty() => f32::MIN,
+                Some(prior) => self
+                    .selection
+                    .unvisited_score(prior, parent_visits, self.expl),
+                None => return node_index,
+            };
+            max_index = node_index;
             for &child_index in children {
                 let child = &self.nodes[child_index];
-                let ucb = child.calculate_ucb(parent_visits, self.expl);
+                let ucb = match child.proof {
+                    Some(Proof::Win) => f32::INFINITY,
+                    Some(Proof::Loss) => f32::NEG_INFINITY,
+                    _ => self.selection.score(
+                        &child.stats(self.rave_equivalence),
+                        parent_visits,
+                        self.expl,
+                    ),
+                };
 
                 if ucb > max_ucb {
                     max_ucb = ucb;
@@ -133,7 +613,6 @@ impl MCTS {
                 return node_index;
             }
             node_index = max_index;
-            max_ucb = std::f32::MIN;
             depth += 1;
         }
     }
@@ -145,97 +624,426 @@ impl MCTS {
 
         if untried_actions.is_empty() {
             // No actions to try add skip node
-            let new_state = self.nodes[node_index].state.clone().do_action(None);
+            let new_state = self.nodes[node_index].state.do_action(None);
             self.add_node(new_state, None, Some(node_index));
             self.tree[node_index].push(self.size - 1);
 
             // Return the new node's index
-            return self.size - 1;
+            self.size - 1
         } else {
-            // Pick one random action to expand (not all at once)
-            let mut rng = rand::thread_rng();
-            let action_index = rng.gen_range(0..untried_actions.len());
+            // Pick one action to expand (not all at once), the most likely one for policies
+            // using priors and a random one otherwise
+            let action_index = if self.selection.uses_priors() {
+                let node = &mut self.nodes[node_index];
+                if node.priors.len() != untried_actions.len() {
+                    node.priors = self.prior.priors(&node.state, &untried_actions);
+                    normalize(&mut node.priors);
+                }
+                (0..node.priors.len())
+                    .max_by(|&a, &b| node.priors[a].total_cmp(&node.priors[b]))
+                    .expect("There are untried actions")
+            } else {
+                self.rng.gen_range(0..untried_actions.len())
+            };
             let action = untried_actions[action_index].clone();
 
             // Remove this action from untried_actions in the original node
-            self.nodes[node_index].untried_actions.remove(action_index);
+            let node = &mut self.nodes[node_index];
+            node.untried_actions.remove(action_index);
+            let prior = match node.priors.is_empty() {
+                true => 1.0,
+                false => node.priors.remove(action_index),
+            };
 
             // Create a new node with this action
-            let new_state = self.nodes[node_index]
-                .state
-                .clone()
-                .do_action(Some(action.clone()));
+            let new_state = node.state.do_action(Some(action.clone()));
             self.add_node(new_state, Some(action), Some(node_index));
+            self.nodes[self.size - 1].prior = prior;
             self.tree[node_index].push(self.size - 1);
 
             // Return the new node's index
-            return self.size - 1;
+            self.size - 1
         }
     }
 
     // Simulates a game from the given node and returns the result
-    fn simulate(&mut self, node_index: usize) -> (Color, isize) {
-        if let Some(node) = self.nodes.get_mut(node_index) {
-            let mut node_state = node.state.clone();
-            let mut score = simulate_game(&mut node_state);
-            if self.color != node.state.next_turn {
-                score *= -1;
-            }
-            node.update_node((node.state.next_turn, score));
-            return (node_state.next_turn, score);
-        }
-        panic!("Node not found");
+    fn simulate(&mut self, node_index: usize) -> LeafValue {
+        let node_state = self.nodes.get(node_index).expect("Node not found").state;
+        self.playout.clear();
+        let white_result = match self.rave_equivalence {
+            None => simulate_game_with(&node_state, &mut self.rng),
+            Some(_) => simulate_game_recording(&node_state, &mut self.rng, &mut self.playout),
+        };
+        LeafValue::single(white_result as f32)
     }
 
     // Updates the nodes in the MCTS from the given child node to the root based on the result of a simulated game
-    fn backpropagate(&mut self, child_index: usize, result: (Color, isize)) {
-        let mut current_node: &mut Node;
-        let mut parent_index: Option<usize> = self.parents.get(child_index).unwrap().clone();
-        while parent_index.is_some() {
-            current_node = self
-                .nodes
-                .get_mut(parent_index.unwrap())
-                .expect("Parent doesn't exist");
-            current_node.update_node(result);
-            let tmp = parent_index.clone();
-            parent_index = *self
+    // With RAVE every child of a node on the way is also updated if its move was played later on
+    fn backpropagate(&mut self, child_index: usize, value: LeafValue) {
+        // The moves played below the current node, by color and field
+        let mut played = self.rave_equivalence.map(|_| {
+            let mut played = vec![false; 2 * N * N];
+            for action in &self.playout {
+                played[move_slot::<N>(action)] = true;
+            }
+            played
+        });
+        let mut node_index = Some(child_index);
+        while let Some(index) = node_index {
+            self.nodes
+                .get_mut(index)
+                .expect("Node doesn't exist")
+                .update_node(&value);
+            if let Some(played) = &mut played {
+                for &child in &self.tree[index] {
+                    let child = &mut self.nodes[child];
+                    if child
+                        .action
+                        .as_ref()
+                        .is_some_and(|a| played[move_slot::<N>(a)])
+                    {
+                        child.update_rave(&value);
+                    }
+                }
+                if let Some(action) = &self.nodes[index].action {
+                    played[move_slot::<N>(action)] = true;
+                }
+            }
+            node_index = *self
                 .parents
-                .get(tmp.unwrap())
-                .expect("Error fetching parent of parent");
+                .get(index)
+                .expect("Error fetching parent of node");
         }
     }
 
+    // The child of the given node the search would play, by `choice_rank`, ties go to the first child
+    fn best_child(&self, node_index: usize) -> Option<usize> {
+        self.tree[node_index]
+            .iter()
+            .rev()
+            .copied()
+            .max_by_key(|&child| {
+                let node = &self.nodes[child];
+                choice_rank(node.proof, node.visits)
+            })
+    }
+
     // Selects the best action from the given node in the MCTS based on the number of visits
-    // It returns the best action found or an error if no action was found
+    // Without an expanded move any legal move is returned, an error only if the player has to pass
     fn get_best_choice(&self, from_index: usize) -> Result<Action, ()> {
-        let mut best_index = 0;
-        let mut max_visits = 0;
-        for index in self
-            .tree
-            .get(from_index)
-            .expect("Empty list of children when getting best choice")
-            .iter()
-            .clone()
+        let from_state = self.nodes[from_index].state;
+        match self
+            .best_child(from_index)
+            .and_then(|child| self.nodes[child].action.clone())
         {
-            let node = self
-                .nodes
-                .get(*index)
-                .expect("MCST, choice: node index doesnt exists");
-            if node.visits > max_visits {
-                best_index = index.clone();
-                max_visits = node.visits;
-            }
-        }
-        let best_node = self.nodes.get(best_index).unwrap().clone();
-        if best_node.action.is_none() {
-            return Err(());
+            Some(action) if action.color == from_state.next_turn => Ok(action),
+            _ => from_state.get_actions().into_iter().next().ok_or(()),
+        }
+    }
+}
+
+// Orders the moves by how good a choice they are, a proven win always comes first and a proven
+// loss last, the other moves by their visits
+fn choice_rank(proof: Option<Proof>, visits: usize) -> (u8, usize) {
+    let rank = match proof {
+        Some(Proof::Win) => 2,
+        Some(Proof::Loss) => 0,
+        _ => 1,
+    };
+    (rank, visits)
+}
+
+// The index of a move among all moves of both colors, for the RAVE statistics
+fn move_slot<const N: usize>(action: &Action) -> usize {
+    let color = match action.color {
+        Color::BLACK => 0,
+        Color::WHITE => 1,
+    };
+    (color * N + action.position.y) * N + action.position.x
+}
+
+#[cfg(test)]
+mod mcts_tests {
+    use super::*;
+    use crate::mlp::Mlp;
+    use crate::othello::Position;
+    use crate::selection::DEFAULT_RAVE_EQUIVALENCE;
+
+    #[test]
+    fn test_search_report() {
+        let mut mcts: MCTS = MCTS::new(Color::BLACK, 1.0);
+        let state = State::new();
+        let (choice, report) = mcts.search_with_report(state, 200, |_, _, _| {});
+        let choice = choice.unwrap();
+        assert_eq!(report.root_visits, 200);
+        assert_eq!(report.moves.len(), 4);
+        assert_eq!(report.moves.iter().map(|m| m.visits).sum::<usize>(), 200);
+        assert!(report.moves.windows(2).all(|m| m[0].visits >= m[1].visits));
+        assert_eq!(report.moves[0].action, Some(choice.clone()));
+        assert_eq!(report.principal_variation.first(), Some(&Some(choice)));
+        assert!(report
+            .moves
+            .iter()
+            .all(|m| (0.0..=1.0).contains(&m.win_rate())));
+    }
+    #[test]
+    fn test_ponder_while() {
+        let mut mcts: MCTS = MCTS::new(Color::BLACK, 1.0);
+        let state: State = State::new();
+        let state = state.do_action(state.get_actions().first().cloned());
+        let (waited, iterations) = mcts.ponder_while(state, || {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            "waited"
+        });
+        assert_eq!(waited, "waited");
+        assert!(iterations > 0);
+        assert_eq!(mcts.report(&state).unwrap().root_visits, iterations);
+
+        // Searching from the opponent's reply starts from the statistics gathered while pondering
+        let reply = mcts.report(&state).unwrap().moves[0].clone();
+        let next = state.do_action(reply.action);
+        _ = mcts.search(next, 10, |_, _, _| {});
+        assert_eq!(mcts.report(&next).unwrap().root_visits, reply.visits + 10);
+    }
+    #[test]
+    fn test_report_unknown_state() {
+        let mcts: MCTS = MCTS::new(Color::BLACK, 1.0);
+        assert!(mcts.report(&State::new()).is_none());
+    }
+    #[test]
+    fn test_status_reports_side_to_move() {
+        let state: State = State::new().do_action(None);
+        let reported = std::cell::Cell::new(None);
+        let mut mcts: MCTS = MCTS::new(Color::BLACK, 1.0);
+        let _ = mcts.search(state, 10, |_, _, color| reported.set(Some(*color)));
+        assert_eq!(reported.get(), Some(Color::WHITE));
+    }
+    #[test]
+    fn test_tree_serves_both_sides() {
+        // White wins by playing (0,3) and loses by playing (2,2)
+        let state: State<4> = "OO-O OXOX XO-O -OXO O".parse().unwrap();
+        for color in [Color::BLACK, Color::WHITE] {
+            let mut mcts: MCTS<4> = MCTS::new(color, 1.0);
+            let (choice, report) = mcts.search_with_report(state, 500, |_, _, _| {});
+            assert_eq!(choice.unwrap().position, Position::new(0, 3));
+            assert!(report.moves[0].mean_value > 0.0);
+
+            // The same tree searches for black after white's mistake
+            let mistake = state.do_action(Some(Action::new(Color::WHITE, Position::new(2, 2))));
+            let (_, report) = mcts.search_with_report(mistake, 500, |_, _, _| {});
+            assert!(report.moves[0].mean_value > 0.0);
+        }
+    }
+    #[test]
+    fn test_selection_policies() {
+        // White wins by playing the corner (0,3), the priors prefer the losing (2,2)
+        let state: State<4> = "OO-O OXOX XO-O -OXO O".parse().unwrap();
+        let misleading = |_: &State<4>, actions: &[Action]| -> Vec<f32> {
+            actions
+                .iter()
+                .map(|a| {
+                    if a.position == Position::new(2, 2) {
+                        0.9
+                    } else {
+                        0.1
+                    }
+                })
+                .collect()
         };
-        let best_action = best_node.action.unwrap().clone();
-        let from_state = self.nodes.get(from_index).unwrap().clone().state;
-        if from_state.next_turn != best_action.color {
-            return Err(());
-        } else {
-            Ok(best_action.clone())
+        for policy in [
+            SelectionPolicy::Ucb1,
+            SelectionPolicy::Ucb1Tuned,
+            SelectionPolicy::Puct,
+            SelectionPolicy::ProgressiveBias,
+        ] {
+            let mut mcts: MCTS<4> = MCTS::new(Color::WHITE, 1.0)
+                .with_selection(policy)
+                .with_prior(misleading);
+            let choice = mcts.search(state, 500, |_, _, _| {});
+            assert_eq!(choice.unwrap().position, Position::new(0, 3), "{policy:?}");
+        }
+    }
+    #[test]
+    fn test_priors_guide_the_selection() {
+        // White wins by playing (0,3), the prior finds it and leaves (2,2) unexpanded while
+        // the search goes deeper below the winning move
+        let state: State<4> = "OO-O OXOX XO-O -OXO O".parse().unwrap();
+        let corner = |_: &State<4>, actions: &[Action]| -> Vec<f32> {
+            actions
+                .iter()
+                .map(|a| {
+                    if a.position == Position::new(0, 3) {
+                        0.99
+                    } else {
+                        0.01
+                    }
+                })
+                .collect()
+        };
+        let mut mcts: MCTS<4> = MCTS::new(Color::WHITE, 1.0)
+            .with_seed(1)
+            .with_selection(SelectionPolicy::Puct)
+            .with_prior(corner);
+        let choice = mcts.search(state, 30, |_, _, _| {});
+        assert_eq!(choice.unwrap().position, Position::new(0, 3));
+        let root = mcts.state_map[&state];
+        assert_eq!(mcts.tree[root].len(), 1);
+        assert!(mcts.size > 2);
+
+        // Without priors every move is expanded first
+        let mut mcts: MCTS<4> = MCTS::new(Color::WHITE, 1.0).with_prior(corner);
+        let _ = mcts.search(state, 30, |_, _, _| {});
+        assert_eq!(mcts.tree[mcts.state_map[&state]].len(), 2);
+    }
+    #[test]
+    fn test_rave_search() {
+        let state: State<4> = "OO-O OXOX XO-O -OXO O".parse().unwrap();
+        let mut mcts: MCTS<4> = MCTS::new(Color::WHITE, 1.0).with_rave(DEFAULT_RAVE_EQUIVALENCE);
+        let choice = mcts.search(state, 300, |_, _, _| {});
+        assert_eq!(choice.unwrap().position, Position::new(0, 3));
+        // Every game through a move counts for its RAVE statistics as well
+        let root = mcts.state_map[&state];
+        for &child in &mcts.tree[root] {
+            assert!(mcts.nodes[child].rave_visits >= mcts.nodes[child].visits);
         }
+
+        let mut plain: MCTS<4> = MCTS::new(Color::WHITE, 1.0);
+        let _ = plain.search(state, 300, |_, _, _| {});
+        assert!(plain.nodes.iter().all(|node| node.rave_visits == 0));
+    }
+    #[test]
+    fn test_solver_proves_results() {
+        // White wins by playing (0,3) and loses by playing (2,2)
+        let state: State<4> = "OO-O OXOX XO-O -OXO O".parse().unwrap();
+        let mut mcts: MCTS<4> = MCTS::new(Color::WHITE, 1.0);
+        let (choice, report) = mcts.search_with_report(state, 200, |_, _, _| {});
+        assert_eq!(choice.unwrap().position, Position::new(0, 3));
+        assert_eq!(report.moves[0].proof, Some(Proof::Win));
+        // Black made the move leading to the searched state, which is lost for them
+        assert_eq!(mcts.nodes[mcts.state_map[&state]].proof, Some(Proof::Loss));
+
+        // After white's mistake black has a proven win
+        let mistake = state.do_action(Some(Action::new(Color::WHITE, Position::new(2, 2))));
+        let (_, report) = mcts.search_with_report(mistake, 200, |_, _, _| {});
+        assert!(report.moves.iter().any(|m| m.proof == Some(Proof::Win)));
+        assert_eq!(report.moves[0].proof, Some(Proof::Win));
+
+        // Searching a finished game proves it without expanding it
+        let mut finished = state;
+        while !finished.is_game_over() {
+            finished = finished.do_action(finished.get_actions().first().cloned());
+        }
+        let mut mcts: MCTS<4> = MCTS::new(Color::WHITE, 1.0);
+        assert!(mcts.search(finished, 10, |_, _, _| {}).is_err());
+        assert_eq!(mcts.size, 1);
+        assert!(mcts.nodes[0].proof.is_some());
+    }
+    #[test]
+    fn test_report_ranks_proven_wins_first() {
+        let state: State<4> = "OO-O OXOX XO-O -OXO O".parse().unwrap();
+        let mut mcts: MCTS<4> = MCTS::new(Color::WHITE, 1.0).with_seed(2);
+        let _ = mcts.search(state, 200, |_, _, _| {});
+        // The unproven move has more visits than the proven win
+        let root = mcts.state_map[&state];
+        let children = mcts.tree[root].clone();
+        let win = *children
+            .iter()
+            .find(|&&child| mcts.nodes[child].proof == Some(Proof::Win))
+            .unwrap();
+        let other = *children.iter().find(|&&child| child != win).unwrap();
+        mcts.nodes[other].proof = None;
+        mcts.nodes[other].visits = mcts.nodes[win].visits + 100;
+
+        let report = mcts.report(&state).unwrap();
+        let best = report.moves[0].clone();
+        assert_eq!(best.proof, Some(Proof::Win));
+        assert!(best.visits < report.moves[1].visits);
+        assert_eq!(best.action, mcts.get_best_choice(root).ok());
+        assert_eq!(report.principal_variation[0], best.action);
+    }
+    #[test]
+    fn test_leaf_parallel_search() {
+        let state: State<4> = "OO-O OXOX XO-O -OXO O".parse().unwrap();
+        let mut mcts: MCTS<4> = MCTS::new(Color::WHITE, 1.0)
+            .with_seed(3)
+            .with_leaf_parallel(8, 2);
+        let (choice, report) = mcts.search_with_report(state, 20, |_, _, _| {});
+        assert_eq!(choice.unwrap().position, Position::new(0, 3));
+        // Expanded leaves count all of their games, proven ones a single result
+        assert!(report.root_visits > 20);
+    }
+    #[test]
+    fn test_batched_leaves() {
+        // Records the largest batch of leaves and values every leaf as a draw
+        struct Batches(Arc<std::sync::atomic::AtomicUsize>);
+        impl LeafEvaluator<8> for Batches {
+            fn evaluate(&mut self, leaves: &[State<8>]) -> Vec<LeafValue> {
+                self.0.fetch_max(leaves.len(), Ordering::Relaxed);
+                vec![LeafValue::single(0.0); leaves.len()]
+            }
+        }
+        let largest = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let mut mcts: MCTS = MCTS::new(Color::BLACK, 1.0)
+            .with_evaluator(Batches(largest.clone()))
+            .with_batch_size(4);
+        let state = State::new();
+        let (_, report) = mcts.search_with_report(state, 10, |_, _, _| {});
+        assert_eq!(largest.load(Ordering::Relaxed), 4);
+        assert_eq!(report.root_visits, 40);
+        // The virtual losses spread the first batch over all moves and are taken back after it
+        assert_eq!(report.moves.len(), 4);
+        assert!(mcts.nodes.iter().all(|node| node.virtual_losses == 0));
+
+        let state: State<4> = "OO-O OXOX XO-O -OXO O".parse().unwrap();
+        let mut mcts: MCTS<4> = MCTS::new(Color::WHITE, 1.0)
+            .with_seed(3)
+            .with_leaf_parallel(2, 1)
+            .with_batch_size(4);
+        let choice = mcts.search(state, 20, |_, _, _| {});
+        assert_eq!(choice.unwrap().position, Position::new(0, 3));
+    }
+    #[test]
+    fn test_state_evaluator_search() {
+        let state: State<4> = "OO-O OXOX XO-O -OXO O".parse().unwrap();
+        for rollout_weight in [0.0, 0.5] {
+            let mut mcts: MCTS<4> = MCTS::new(Color::WHITE, 1.0)
+                .with_seed(5)
+                .with_selection(SelectionPolicy::Puct)
+                .with_state_evaluator(Mlp::random(&[8], 2).unwrap(), rollout_weight);
+            let (choice, report) = mcts.search_with_report(state, 100, |_, _, _| {});
+            // The solver corrects whatever the untrained network thinks
+            assert_eq!(choice.unwrap().position, Position::new(0, 3));
+            assert_eq!(report.moves[0].proof, Some(Proof::Win));
+        }
+    }
+    #[test]
+    fn test_state_evaluator_runs_once_per_leaf() {
+        // Counts the evaluations and values every state as a draw
+        struct Counter(Arc<std::sync::atomic::AtomicUsize>);
+        impl Evaluator<8> for Counter {
+            fn evaluate(&self, _state: &State<8>) -> crate::evaluation::Evaluation {
+                self.0.fetch_add(1, Ordering::Relaxed);
+                crate::evaluation::Evaluation {
+                    value: 0.0,
+                    priors: vec![1.0; 64],
+                }
+            }
+        }
+        let evaluations = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let mut mcts: MCTS = MCTS::new(Color::BLACK, 1.0)
+            .with_selection(SelectionPolicy::Puct)
+            .with_state_evaluator(Counter(evaluations.clone()), 0.0);
+        let _ = mcts.search(State::new(), 50, |_, _, _| {});
+        // Every leaf once for its value and priors, and the root once for its priors
+        assert_eq!(evaluations.load(Ordering::Relaxed), 50 + 1);
+    }
+    #[test]
+    fn test_seeded_search() {
+        let state: State = State::new();
+        let mut first: MCTS = MCTS::new(Color::BLACK, 1.0).with_seed(7);
+        let mut second: MCTS = MCTS::new(Color::BLACK, 1.0).with_seed(7);
+        let first = first.search_with_report(state, 500, |_, _, _| {});
+        let second = second.search_with_report(state, 500, |_, _, _| {});
+        assert_eq!(first, second);
     }
 }
@@ -0,0 +1,265 @@
+use crate::evaluation::{Evaluation, Evaluator};
+use crate::othello::{Position, State};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde_json::{json, Value};
+use std::fmt;
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum MlpError {
+    // The weights file couldn't be read
+    Io(std::io::Error),
+    // The weights file isn't valid JSON
+    Json(serde_json::Error),
+    // The weights don't describe a network for the board, names the offending part
+    Invalid(String),
+}
+impl fmt::Display for MlpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MlpError::Io(e) => write!(f, "Couldn't read the weights: {e}"),
+            MlpError::Json(e) => write!(f, "Invalid weights file: {e}"),
+            MlpError::Invalid(part) => write!(f, "Invalid network weights: {part}"),
+        }
+    }
+}
+impl std::error::Error for MlpError {}
+
+// A fully connected layer, the weights are stored row by row with one row per output
+#[derive(Debug, Clone, PartialEq)]
+struct Layer {
+    inputs: usize,
+    weights: Vec<f32>,
+    biases: Vec<f32>,
+}
+impl Layer {
+    fn random(inputs: usize, outputs: usize, rng: &mut StdRng) -> Layer {
+        // Uniform weights scaled by the inputs keep the activations in range
+        let bound = (6.0 / inputs as f32).sqrt();
+        Layer {
+            inputs,
+            weights: (0..inputs * outputs)
+                .map(|_| rng.gen_range(-bound..bound))
+                .collect(),
+            biases: vec![0.0; outputs],
+        }
+    }
+
+    fn forward(&self, input: &[f32]) -> Vec<f32> {
+        self.weights
+            .chunks(self.inputs)
+            .zip(&self.biases)
+            .map(|(row, bias)| bias + row.iter().zip(input).map(|(w, x)| w * x).sum::<f32>())
+            .collect()
+    }
+
+    fn from_json(json: &Value, inputs: usize, name: &str) -> Result<Layer, MlpError> {
+        let invalid = |what: &str| MlpError::Invalid(format!("{what} of {name}"));
+        let numbers = |value: &Value| -> Option<Vec<f32>> {
+            value
+                .as_array()?
+                .iter()
+                .map(|n| n.as_f64().map(|n| n as f32))
+                .collect()
+        };
+        let rows = json["weights"]
+            .as_array()
+            .ok_or_else(|| invalid("weights"))?;
+        // A layer without inputs or outputs can't pass anything on
+        if inputs == 0 || rows.is_empty() {
+            return Err(invalid("no inputs or outputs in the weights"));
+        }
+        let mut weights = Vec::with_capacity(rows.len() * inputs);
+        for row in rows {
+            let row = numbers(row).ok_or_else(|| invalid("weights"))?;
+            if row.len() != inputs {
+                return Err(invalid(&format!("expected {inputs} inputs in the weights")));
+            }
+            weights.extend(row);
+        }
+        let biases = numbers(&json["biases"]).ok_or_else(|| invalid("biases"))?;
+        if biases.len() != rows.len() {
+            return Err(invalid(&format!("expected {} biases", rows.len())));
+        }
+        Ok(Layer {
+            inputs,
+            weights,
+            biases,
+        })
+    }
+
+    fn to_json(&self) -> Value {
+        let rows: Vec<&[f32]> = self.weights.chunks(self.inputs).collect();
+        json!({ "weights": rows, "biases": self.biases })
+    }
+}
+
+// A small multilayer perceptron evaluating states on the CPU
+// It sees the discs of the player to move, the discs of the opponent and the legal moves,
+// passes them through hidden ReLU layers and outputs a tanh value and softmax move priors
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mlp<const N: usize> {
+    hidden: Vec<Layer>,
+    value: Layer,
+    policy: Layer,
+}
+
+impl<const N: usize> Mlp<N> {
+    // Number of inputs of the network, three planes of the board
+    pub const INPUTS: usize = 3 * N * N;
+
+    // A network with random weights and the given sizes of the hidden layers, which can't be empty
+    pub fn random(hidden: &[usize], seed: u64) -> Result<Mlp<N>, MlpError> {
+        if let Some(i) = hidden.iter().position(|&outputs| outputs == 0) {
+            return Err(MlpError::Invalid(format!(
+                "hidden layer {i} has no outputs"
+            )));
+        }
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut inputs = Self::INPUTS;
+        let mut layers = Vec::new();
+        for &outputs in hidden {
+            layers.push(Layer::random(inputs, outputs, &mut rng));
+            inputs = outputs;
+        }
+        Ok(Mlp {
+            hidden: layers,
+            value: Layer::random(inputs, 1, &mut rng),
+            policy: Layer::random(inputs, N * N, &mut rng),
+        })
+    }
+
+    // Loads the weights from a JSON file as written by `to_json`
+    pub fn load(path: impl AsRef<Path>) -> Result<Mlp<N>, MlpError> {
+        let text = std::fs::read_to_string(path).map_err(MlpError::Io)?;
+        let json: Value = serde_json::from_str(&text).map_err(MlpError::Json)?;
+        Self::from_json(&json)
+    }
+
+    // The weights are given as {"board_size", "hidden": [layer], "value": layer, "policy": layer},
+    // every layer as {"weights": [[f32; inputs]; outputs], "biases": [f32; outputs]}
+    pub fn from_json(json: &Value) -> Result<Mlp<N>, MlpError> {
+        match json["board_size"].as_u64() {
+            Some(size) if size as usize == N => {}
+            _ => return Err(MlpError::Invalid(format!("expected board_size {N}"))),
+        }
+        let mut inputs = Self::INPUTS;
+        let mut hidden = Vec::new();
+        let layers = json["hidden"]
+            .as_array()
+            .ok_or_else(|| MlpError::Invalid("hidden layers".to_string()))?;
+        for (i, layer) in layers.iter().enumerate() {
+            let layer = Layer::from_json(layer, inputs, &format!("hidden layer {i}"))?;
+            inputs = layer.biases.len();
+            hidden.push(layer);
+        }
+        let value = Layer::from_json(&json["value"], inputs, "the value head")?;
+        let policy = Layer::from_json(&json["policy"], inputs, "the policy head")?;
+        if value.biases.len() != 1 || policy.biases.len() != N * N {
+            return Err(MlpError::Invalid(format!(
+                "expected 1 value and {} policy outputs",
+                N * N
+            )));
+        }
+        Ok(Mlp {
+            hidden,
+            value,
+            policy,
+        })
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "board_size": N,
+            "hidden": self.hidden.iter().map(Layer::to_json).collect::<Vec<_>>(),
+            "value": self.value.to_json(),
+            "policy": self.policy.to_json(),
+        })
+    }
+}
+
+impl<const N: usize> Evaluator<N> for Mlp<N> {
+    fn evaluate(&self, state: &State<N>) -> Evaluation {
+        let mut activations = encode_state(state);
+        for layer in &self.hidden {
+            activations = layer.forward(&activations);
+            activations.iter_mut().for_each(|a| *a = a.max(0.0));
+        }
+        let value = self.value.forward(&activations)[0].tanh();
+        let mut priors = self.policy.forward(&activations);
+        let max = priors.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+        priors.iter_mut().for_each(|p| *p = (*p - max).exp());
+        let total: f32 = priors.iter().sum();
+        priors.iter_mut().for_each(|p| *p /= total);
+        Evaluation { value, priors }
+    }
+}
+
+// Encodes the state for the player to move as three planes of the board, row by row:
+// their discs, the opponent's discs and their legal moves
+pub fn encode_state<const N: usize>(state: &State<N>) -> Vec<f32> {
+    let mut planes = vec![0.0; 3 * N * N];
+    for y in 0..N {
+        for x in 0..N {
+            match state.disc_at(Position::new(x, y)) {
+                Some(color) if color == state.next_turn => planes[y * N + x] = 1.0,
+                Some(_) => planes[N * N + y * N + x] = 1.0,
+                None => {}
+            }
+        }
+    }
+    for action in state.get_actions() {
+        planes[2 * N * N + action.position.y * N + action.position.x] = 1.0;
+    }
+    planes
+}
+
+#[cfg(test)]
+mod mlp_tests {
+    use super::*;
+
+    #[test]
+    fn test_evaluate_and_round_trip() {
+        let mlp: Mlp<4> = Mlp::random(&[16, 8], 1).unwrap();
+        let state: State<4> = "OO-O OXOX XO-O -OXO O".parse().unwrap();
+        let evaluation = mlp.evaluate(&state);
+        assert!(evaluation.value.abs() <= 1.0);
+        assert_eq!(evaluation.priors.len(), 16);
+        assert!((evaluation.priors.iter().sum::<f32>() - 1.0).abs() < 1e-5);
+
+        let loaded: Mlp<4> = Mlp::from_json(&mlp.to_json()).unwrap();
+        assert_eq!(loaded, mlp);
+        assert_eq!(loaded.evaluate(&state), evaluation);
+
+        // The network has to match the board
+        assert!(Mlp::<8>::from_json(&mlp.to_json()).is_err());
+        let mut broken = mlp.to_json();
+        broken["policy"]["biases"] = json!([0.0]);
+        assert!(Mlp::<4>::from_json(&broken).is_err());
+    }
+    #[test]
+    fn test_empty_layers() {
+        assert!(matches!(
+            Mlp::<4>::random(&[8, 0], 1),
+            Err(MlpError::Invalid(_))
+        ));
+        let mut empty = Mlp::<4>::random(&[8], 1).unwrap().to_json();
+        empty["hidden"][0] = json!({ "weights": [], "biases": [] });
+        assert!(matches!(
+            Mlp::<4>::from_json(&empty),
+            Err(MlpError::Invalid(_))
+        ));
+    }
+    #[test]
+    fn test_encode_state() {
+        let state: State<4> = "OO-O OXOX XO-O -OXO O".parse().unwrap();
+        let planes = encode_state(&state);
+        // White moves, so the first plane holds the white discs
+        assert_eq!(planes[0], 1.0);
+        assert_eq!(planes[16 + 5], 1.0);
+        assert_eq!(planes[32 + 12], 1.0);
+        // 13 discs and the two moves of white
+        assert_eq!(planes.iter().sum::<f32>(), 13.0 + 2.0);
+    }
+}
@@ -1,7 +1,12 @@
+use crate::render::{render_state, RenderOptions};
 use rand::Rng;
-use std::{fmt, isize, u16, usize};
+use std::fmt;
+use std::str::FromStr;
 
-const BOARD_SIZE: usize = 8;
+// The standard board size, used when no size is given
+pub const BOARD_SIZE: usize = 8;
+// The largest supported board size, limited by the bits available in a `Row`
+pub const MAX_BOARD_SIZE: usize = 16;
 const FIELD_SIZE: usize = 2;
 
 #[derive(Debug, Clone)]
@@ -19,18 +24,158 @@ impl fmt::Display for OccupiedFieldError {
     }
 }
 
+// Reasons an action can be rejected by `State::try_action`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OthelloError {
+    // The position is not on the board
+    OutOfBounds(Position),
+    // The position already holds a disc
+    Occupied(Position),
+    // Placing a disc on the position would not flip any opponent discs
+    NoFlips(Position),
+    // The action was made by the wrong player, holds the color whose turn it is
+    WrongColor(Color),
+    // The game has already ended
+    GameOver,
+}
+impl fmt::Display for OthelloError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OthelloError::OutOfBounds(pos) => {
+                write!(f, "Position ({}, {}) is outside the board", pos.x, pos.y)
+            }
+            OthelloError::Occupied(pos) => {
+                write!(f, "Position ({}, {}) is already occupied", pos.x, pos.y)
+            }
+            OthelloError::NoFlips(pos) => {
+                write!(f, "Position ({}, {}) doesn't flip any discs", pos.x, pos.y)
+            }
+            OthelloError::WrongColor(color) => write!(f, "It is {:?}'s turn", color),
+            OthelloError::GameOver => write!(f, "The game is over"),
+        }
+    }
+}
+impl std::error::Error for OthelloError {}
+
+// Reasons a starting position or handicap can't be set up
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PositionError {
+    // The encoding doesn't hold one field per board position
+    WrongLength { expected: usize, found: usize },
+    // The encoding holds a character that isn't a disc or an empty field
+    InvalidCell(char),
+    // The side to move isn't a known color
+    InvalidTurn(char),
+    // The handicap couldn't be parsed or asks for more discs than there are free corners
+    InvalidHandicap(String),
+    // The board has more empty fields than the remaining moves can count, e.g. an empty 16x16 board
+    TooManyEmptyFields(usize),
+}
+impl fmt::Display for PositionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PositionError::WrongLength { expected, found } => {
+                write!(f, "Expected {expected} fields but found {found}")
+            }
+            PositionError::InvalidCell(c) => write!(f, "'{c}' is not a valid field"),
+            PositionError::InvalidTurn(c) => write!(f, "'{c}' is not a valid side to move"),
+            PositionError::InvalidHandicap(h) => write!(f, "Invalid handicap '{h}'"),
+            PositionError::TooManyEmptyFields(n) => {
+                write!(
+                    f,
+                    "{n} empty fields are more than the {} supported",
+                    u8::MAX
+                )
+            }
+        }
+    }
+}
+impl std::error::Error for PositionError {}
+
+// Reasons a board sent by the game server can't be read by `parse_state`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    // A required field is missing or doesn't have the expected type
+    MissingField(&'static str),
+    // An optional field doesn't have the expected type
+    InvalidField(&'static str),
+    // The board or one of its lines doesn't hold one entry per field
+    WrongSize { expected: usize, found: usize },
+    // A line of the board isn't an array, holds the index of the line
+    InvalidLine(usize),
+    // A cell holds something other than 1, 0 or -1
+    InvalidCell { position: Position, value: String },
+    // The board has more empty fields than the remaining moves can count
+    TooManyEmptyFields(usize),
+}
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::MissingField(field) => write!(f, "Missing or invalid field '{field}'"),
+            ParseError::InvalidField(field) => write!(f, "Invalid field '{field}'"),
+            ParseError::WrongSize { expected, found } => {
+                write!(f, "Expected {expected} entries but found {found}")
+            }
+            ParseError::InvalidLine(i) => write!(f, "Line {i} of the board is not an array"),
+            ParseError::InvalidCell { position, value } => write!(
+                f,
+                "Invalid cell {value} at ({}, {})",
+                position.x, position.y
+            ),
+            ParseError::TooManyEmptyFields(n) => {
+                write!(
+                    f,
+                    "{n} empty fields are more than the {} supported",
+                    u8::MAX
+                )
+            }
+        }
+    }
+}
+impl std::error::Error for ParseError {}
+
+// How the nested arrays of a board sent by the game server map to positions
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BoardOrientation {
+    // board[x][y], the layout used by the game server
+    #[default]
+    Columns,
+    // board[y][x]
+    Rows,
+}
+
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
 pub enum Color {
     BLACK,
     WHITE,
 }
 impl Color {
-    fn bitmask(&self) -> u16 {
+    fn bitmask(&self) -> u32 {
         match *self {
             Color::BLACK => 0b010,
             Color::WHITE => 0b001,
         }
     }
+    // The character used for the color in position encodings
+    fn symbol(&self) -> char {
+        match self {
+            Color::BLACK => 'X',
+            Color::WHITE => 'O',
+        }
+    }
+    fn from_symbol(c: char) -> Option<Color> {
+        match c {
+            'X' | 'x' | 'B' | 'b' | '*' => Some(Color::BLACK),
+            'O' | 'o' | 'W' | 'w' => Some(Color::WHITE),
+            _ => None,
+        }
+    }
+    pub fn opponent(&self) -> Color {
+        match self {
+            Color::BLACK => Color::WHITE,
+            Color::WHITE => Color::BLACK,
+        }
+    }
 }
 #[derive(Debug, Clone, Copy)]
 pub enum Direction {
@@ -54,6 +199,19 @@ impl Direction {
         Self::DownLeft,
         Self::DownRight,
     ];
+    // The (x, y) step taken when moving one field in the direction
+    fn offset(&self) -> (isize, isize) {
+        match self {
+            Direction::Left => (-1, 0),
+            Direction::Right => (1, 0),
+            Direction::Up => (0, -1),
+            Direction::Down => (0, 1),
+            Direction::UpLeft => (-1, -1),
+            Direction::UpRight => (1, -1),
+            Direction::DownLeft => (-1, 1),
+            Direction::DownRight => (1, 1),
+        }
+    }
 }
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
 pub struct Position {
@@ -61,65 +219,30 @@ pub struct Position {
     pub y: usize,
 }
 impl Position {
-    fn new(x_coordinate: usize, y_coordinate: usize) -> Option<Position> {
-        match (x_coordinate, y_coordinate) {
-            (x, y) if x >= BOARD_SIZE || y >= BOARD_SIZE => None,
-            (_, _) => Some(Self {
-                x: x_coordinate,
-                y: y_coordinate,
-            }),
+    // Creates a position, use `is_on_board` or `State::try_action` to check its bounds
+    pub fn new(x_coordinate: usize, y_coordinate: usize) -> Position {
+        Self {
+            x: x_coordinate,
+            y: y_coordinate,
         }
     }
-    fn shift(self, dir: Direction) -> Option<Position> {
-        let x = self.x;
-        let y = self.y;
-        match dir {
-            Direction::Up => match y {
-                0 => None,
-                _ => Position::new(x, y - 1),
-            },
-            Direction::Down => match y + 1 {
-                BOARD_SIZE => None,
-                _ => Position::new(x, y + 1),
-            },
-            Direction::Left => match x {
-                0 => None,
-                _ => Position::new(x - 1, y),
-            },
-            Direction::Right => match x + 1 {
-                BOARD_SIZE => None,
-                _ => Position::new(x + 1, y),
-            },
-            Direction::UpLeft => match (x, y) {
-                (0, _) => None,
-                (_, 0) => None,
-                (_, _) => Position::new(x - 1, y - 1),
-            },
-            Direction::UpRight => match (x + 1, y) {
-                (BOARD_SIZE, _) => None,
-                (_, 0) => None,
-                (_, _) => Position::new(x + 1, y - 1),
-            },
-            Direction::DownLeft => match (x, y + 1) {
-                (0, _) => None,
-                (_, BOARD_SIZE) => None,
-                (_, _) => Position::new(x - 1, y + 1),
-            },
-            Direction::DownRight => match (x, y) {
-                (BOARD_SIZE, _) => None,
-                (_, BOARD_SIZE) => None,
-                (_, _) => Position::new(x + 1, y + 1),
-            },
-        }
+    pub fn is_on_board(&self, board_size: usize) -> bool {
+        self.x < board_size && self.y < board_size
+    }
+    fn shift(self, dir: Direction, board_size: usize) -> Option<Position> {
+        let (dx, dy) = dir.offset();
+        let x = self.x.checked_add_signed(dx)?;
+        let y = self.y.checked_add_signed(dy)?;
+        Some(Position::new(x, y)).filter(|pos| pos.is_on_board(board_size))
     }
 }
 
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
 struct Row {
-    value: u16,
+    value: u32,
 }
 impl Row {
-    fn new(val: u16) -> Row {
+    fn new(val: u32) -> Row {
         Self { value: val }
     }
     fn get_pos(&self, pos: usize) -> Option<Color> {
@@ -150,44 +273,50 @@ impl Row {
             }),
         }
     }
-    fn count_colors(&self) -> (isize, isize) {
+    fn count_colors(&self, row_length: usize) -> (isize, isize) {
         let mut w_score = 0;
         let mut b_score = 0;
-        let mut row = self.value.clone();
-        for _ in 0..BOARD_SIZE {
+        let mut row = self.value;
+        for _ in 0..row_length {
             if row & Color::WHITE.bitmask() > 0 {
                 w_score += 1;
             }
             if row & Color::BLACK.bitmask() > 0 {
                 b_score += 1;
             }
-            row = row >> FIELD_SIZE;
+            row >>= FIELD_SIZE;
         }
-        return (w_score, b_score);
+        (w_score, b_score)
     }
 }
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
-struct Board {
-    rows: [Row; BOARD_SIZE as usize],
-}
-impl Board {
-    fn new() -> Board {
-        let mut new_rows = [Row::new(0); BOARD_SIZE as usize];
-        let center = (BOARD_SIZE / 2) - 1;
-        new_rows[center as usize] = Row::new(0b1001 << (center * FIELD_SIZE));
-        new_rows[(center + 1) as usize] = Row::new(0b0110 << (center * FIELD_SIZE));
-        Self { rows: new_rows }
+struct Board<const N: usize> {
+    rows: [Row; N],
+}
+impl<const N: usize> Board<N> {
+    fn new() -> Board<N> {
+        let mut new_board = Self::blank();
+        let center = (N / 2) - 1;
+        new_board.rows[center] = Row::new(0b1001 << (center * FIELD_SIZE));
+        new_board.rows[center + 1] = Row::new(0b0110 << (center * FIELD_SIZE));
+        new_board
     }
-    fn blank() -> Board {
-        let new_rows = [Row::new(0); BOARD_SIZE as usize];
+    fn blank() -> Board<N> {
+        const {
+            assert!(
+                N >= 4 && N <= MAX_BOARD_SIZE && N.is_multiple_of(2),
+                "Board size must be even and between 4 and MAX_BOARD_SIZE"
+            )
+        };
+        let new_rows = [Row::new(0); N];
         Self { rows: new_rows }
     }
-    fn flip_pieces(&self, action: Action, position: Position, dir: Direction) -> Option<Board> {
+    fn flip_pieces(&self, action: &Action, position: Position, dir: Direction) -> Option<Board<N>> {
         let mut to_flip = Vec::new();
         let mut current_pos = position;
 
         // Move in the specified direction, collecting opponent pieces
-        while let Some(next_pos) = current_pos.shift(dir) {
+        while let Some(next_pos) = current_pos.shift(dir, N) {
             match self.rows[next_pos.y].get_pos(next_pos.x) {
                 Some(color) if color != action.color => {
                     // Found an opponent's piece add it to list
@@ -198,7 +327,7 @@ impl Board {
                     // Found own piece flip all the pieces collected
                     if !to_flip.is_empty() {
                         // Create new board with the flipped pieces
-                        let mut new_board = self.clone();
+                        let mut new_board = *self;
 
                         // Flip all pieces in between
                         for pos in to_flip {
@@ -222,26 +351,21 @@ impl Board {
     fn get_empty_positions(&self) -> Vec<Position> {
         let mut positions = Vec::new();
         for (y, row) in self.into_iter().enumerate() {
-            for x in 0..BOARD_SIZE {
-                match row.get_pos(x) {
-                    None => {
-                        positions.push(Position::new(x, y).expect(
-                            "Iterating through board shouldn't be able to get out of bounds",
-                        ))
-                    }
-                    Some(_) => (),
+            for x in 0..N {
+                if row.get_pos(x).is_none() {
+                    positions.push(Position::new(x, y));
                 }
             }
         }
-        return positions;
+        positions
     }
-    fn would_flip_pieces(&self, action: Action, position: Position, dir: Direction) -> bool {
-        match position.shift(dir) {
+    fn would_flip_pieces(&self, action: &Action, position: Position, dir: Direction) -> bool {
+        match position.shift(dir, N) {
             Some(pos_1) => match self.rows[pos_1.y].get_pos(pos_1.x) {
                 Some(color) if color != action.color => {
                     // Found an opponent's piece in this direction
                     let mut current_pos = pos_1;
-                    while let Some(next_pos) = current_pos.shift(dir) {
+                    while let Some(next_pos) = current_pos.shift(dir, N) {
                         match self.rows[next_pos.y].get_pos(next_pos.x) {
                             Some(color) if color == action.color => {
                                 // Found our own piece on the other side
@@ -265,25 +389,25 @@ impl Board {
         }
     }
 }
-impl IntoIterator for Board {
+impl<const N: usize> IntoIterator for Board<N> {
     type Item = Row;
-    type IntoIter = BoardIntoIterator;
+    type IntoIter = BoardIntoIterator<N>;
     fn into_iter(self) -> Self::IntoIter {
         BoardIntoIterator {
-            board: self.clone(),
+            board: self,
             index: 0,
         }
     }
 }
-struct BoardIntoIterator {
-    board: Board,
+struct BoardIntoIterator<const N: usize> {
+    board: Board<N>,
     index: usize,
 }
-impl Iterator for BoardIntoIterator {
+impl<const N: usize> Iterator for BoardIntoIterator<N> {
     type Item = Row;
     fn next(&mut self) -> Option<Self::Item> {
         let result = match self.index {
-            x if x < BOARD_SIZE as usize => self.board.rows[x],
+            x if x < N => self.board.rows[x],
             _ => return None,
         };
         self.index += 1;
@@ -291,76 +415,128 @@ impl Iterator for BoardIntoIterator {
     }
 }
 
+// The state of a game on an N x N board, N defaults to the standard 8 x 8 board
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
-pub struct State {
-    board: Board,
+pub struct State<const N: usize = BOARD_SIZE> {
+    board: Board<N>,
     pub next_turn: Color,
     pub remaining_moves: u8,
     pub prev_player_skipped: bool,
 }
-impl State {
+impl<const N: usize> Default for State<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl<const N: usize> State<N> {
     pub fn new() -> Self {
         Self {
             board: Board::new(),
             next_turn: Color::BLACK,
-            remaining_moves: 121,
+            remaining_moves: (N * N - 4) as u8,
             prev_player_skipped: false,
         }
     }
+    pub fn board_size(&self) -> usize {
+        N
+    }
+    // Returns the color of the disc at the position, None if it is empty or outside the board
+    pub fn disc_at(&self, position: Position) -> Option<Color> {
+        match position.is_on_board(N) {
+            true => self.board.rows[position.y].get_pos(position.x),
+            false => None,
+        }
+    }
+    // Counts the discs of the given color on the board
+    pub fn count_discs(&self, color: Color) -> usize {
+        self.board
+            .into_iter()
+            .map(|row| {
+                let (w, b) = row.count_colors(N);
+                match color {
+                    Color::WHITE => w as usize,
+                    Color::BLACK => b as usize,
+                }
+            })
+            .sum()
+    }
+    // The game is over when the board is full or neither player can make a move
+    pub fn is_game_over(&self) -> bool {
+        if self.remaining_moves == 0 {
+            return true;
+        }
+        let mut opponent = *self;
+        opponent.next_turn = self.next_turn.opponent();
+        self.get_actions().is_empty() && opponent.get_actions().is_empty()
+    }
     pub fn get_actions(&self) -> Vec<Action> {
         let empty_spots = self.board.get_empty_positions();
         let mut actions = Vec::new();
-        if empty_spots.len() == 0 {
+        if empty_spots.is_empty() {
             return actions;
         }
         for pos in empty_spots {
-            let action = Action::new(self.next_turn.clone(), pos);
-            if self.is_valid_action(action.clone()) {
+            let action = Action::new(self.next_turn, pos);
+            if self.is_valid_action(&action) {
                 actions.push(action);
             }
         }
-        return actions;
+        actions
     }
-    fn is_valid_action(&self, action: Action) -> bool {
+    fn is_valid_action(&self, action: &Action) -> bool {
         for dir in Direction::VALUES {
-            if self
-                .board
-                .would_flip_pieces(action.clone(), action.position.clone(), dir)
-            {
+            if self.board.would_flip_pieces(action, action.position, dir) {
                 return true;
             }
         }
         false
     }
 
-    pub fn do_action(&self, action: Option<Action>) -> State {
-        let mut new_state = self.clone();
-        match action {
-            Some(act) => {
-                if new_state.flip_directions(act) {
-                    new_state.remaining_moves -= 1;
-                    new_state.prev_player_skipped = false;
-                } else {
-                    new_state.prev_player_skipped = true;
-                }
-            }
-            None => {
-                new_state.prev_player_skipped = true;
-            }
+    // Plays the given action and returns the resulting state
+    // It returns an error describing why the action is illegal, leaving the state untouched
+    pub fn try_action(&self, action: &Action) -> Result<State<N>, OthelloError> {
+        if self.remaining_moves == 0 {
+            return Err(OthelloError::GameOver);
+        }
+        if action.color != self.next_turn {
+            return Err(OthelloError::WrongColor(self.next_turn));
+        }
+        if !action.position.is_on_board(N) {
+            return Err(OthelloError::OutOfBounds(action.position));
+        }
+        if self.board.rows[action.position.y]
+            .get_pos(action.position.x)
+            .is_some()
+        {
+            return Err(OthelloError::Occupied(action.position));
         }
+        let mut new_state = *self;
+        if !new_state.flip_directions(action) {
+            return Err(OthelloError::NoFlips(action.position));
+        }
+        new_state.prev_player_skipped = false;
+        new_state.next_turn = self.next_turn.opponent();
+        Ok(new_state)
+    }
+
+    // Plays the given action, or passes the turn if it is None
+    // An illegal action is treated as a pass, use `try_action` to find out why it was rejected
+    pub fn do_action(&self, action: Option<Action>) -> State<N> {
+        if let Some(new_state) = action.and_then(|act| self.try_action(&act).ok()) {
+            return new_state;
+        }
+        let mut new_state = *self;
+        new_state.prev_player_skipped = true;
         // If both players had to skip end the game
-        if new_state.prev_player_skipped && self.prev_player_skipped {
+        if self.prev_player_skipped {
             new_state.remaining_moves = 0;
         }
-        new_state.next_turn = match self.next_turn {
-            Color::BLACK => Color::WHITE,
-            Color::WHITE => Color::BLACK,
-        };
+        new_state.next_turn = self.next_turn.opponent();
         new_state
     }
-    fn flip_directions(&mut self, action: Action) -> bool {
+    fn flip_directions(&mut self, action: &Action) -> bool {
         let mut any_flipped = false;
-        let mut new_board = self.board.clone();
+        let mut new_board = self.board;
 
         // Set the piece at the action position
         if let Ok(row) = new_board.rows[action.position.y].set_pos(action.color, action.position.x)
@@ -371,8 +547,7 @@ impl State {
         }
         // Check each direction for pieces to flip
         for dir in Direction::VALUES {
-            if let Some(updated_board) = new_board.flip_pieces(action.clone(), action.position, dir)
-            {
+            if let Some(updated_board) = new_board.flip_pieces(action, action.position, dir) {
                 new_board = updated_board;
                 any_flipped = true;
             }
@@ -400,13 +575,160 @@ impl Action {
     }
 }
 
+// Extra discs placed in the corners for one side before the game starts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Handicap {
+    pub color: Color,
+    pub discs: usize,
+}
+impl FromStr for Handicap {
+    type Err = PositionError;
+    // Parses handicaps written as "<color>:<discs>", e.g. "black:2"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || PositionError::InvalidHandicap(s.to_string());
+        let (color, discs) = s.split_once(':').ok_or_else(invalid)?;
+        let color = match color.trim().to_lowercase().as_str() {
+            "black" | "b" => Color::BLACK,
+            "white" | "w" => Color::WHITE,
+            _ => return Err(invalid()),
+        };
+        let discs = discs.trim().parse().map_err(|_| invalid())?;
+        Ok(Handicap { color, discs })
+    }
+}
+
+impl<const N: usize> State<N> {
+    // Returns a copy of the state with handicap discs added to the free corners
+    // The corners are filled in the order top left, bottom right, top right, bottom left
+    pub fn with_handicap(&self, handicap: Handicap) -> Result<State<N>, PositionError> {
+        let corners = [
+            Position::new(0, 0),
+            Position::new(N - 1, N - 1),
+            Position::new(N - 1, 0),
+            Position::new(0, N - 1),
+        ];
+        let mut new_state = *self;
+        let mut placed = 0;
+        for pos in corners {
+            if placed == handicap.discs {
+                break;
+            }
+            if let Ok(row) = new_state.board.rows[pos.y].set_pos(handicap.color, pos.x) {
+                new_state.board.rows[pos.y] = row;
+                new_state.remaining_moves -= 1;
+                placed += 1;
+            }
+        }
+        if placed < handicap.discs {
+            return Err(PositionError::InvalidHandicap(format!(
+                "{} discs for {:?}",
+                handicap.discs, handicap.color
+            )));
+        }
+        Ok(new_state)
+    }
+}
+
+// Encodes the state as one character per field, row by row, followed by the side to move
+// Black discs are 'X', white discs are 'O' and empty fields are '-'
+impl<const N: usize> fmt::Display for State<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for row in self.board {
+            for x in 0..N {
+                let c = row.get_pos(x).map_or('-', |color| color.symbol());
+                write!(f, "{c}")?;
+            }
+        }
+        write!(f, " {}", self.next_turn.symbol())
+    }
+}
+// Parses positions in the encoding written by `Display`, whitespace is ignored
+// Black may also be written as 'B' or '*', white as 'W' and empty fields as '.' or '_'
+// If the side to move is left out it defaults to black
+impl<const N: usize> FromStr for State<N> {
+    type Err = PositionError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<char> = s.chars().filter(|c| !c.is_whitespace()).collect();
+        if fields.len() != N * N && fields.len() != N * N + 1 {
+            return Err(PositionError::WrongLength {
+                expected: N * N,
+                found: fields.len(),
+            });
+        }
+        let mut new_board = Board::blank();
+        let mut empty_fields: usize = 0;
+        for (i, c) in fields.iter().take(N * N).enumerate() {
+            let (x, y) = (i % N, i / N);
+            match (c, Color::from_symbol(*c)) {
+                (_, Some(color)) => {
+                    new_board.rows[y] = new_board.rows[y]
+                        .set_pos(color, x)
+                        .expect("Each field is only set once");
+                }
+                ('-' | '.' | '_', None) => empty_fields += 1,
+                (c, None) => return Err(PositionError::InvalidCell(*c)),
+            }
+        }
+        let next_turn = match fields.get(N * N) {
+            Some(c) => Color::from_symbol(*c).ok_or(PositionError::InvalidTurn(*c))?,
+            None => Color::BLACK,
+        };
+        let remaining_moves = u8::try_from(empty_fields)
+            .map_err(|_| PositionError::TooManyEmptyFields(empty_fields))?;
+        Ok(State {
+            board: new_board,
+            next_turn,
+            remaining_moves,
+            prev_player_skipped: false,
+        })
+    }
+}
+
+// Builds the starting state of a game from an optional position encoding and handicap
+// Without a position the standard starting position is used
+pub fn starting_state<const N: usize>(
+    position: Option<&str>,
+    handicap: Option<&str>,
+) -> Result<State<N>, PositionError> {
+    let state = match position {
+        Some(encoding) => encoding.parse()?,
+        None => State::new(),
+    };
+    match handicap {
+        Some(handicap) => state.with_handicap(handicap.parse()?),
+        None => Ok(state),
+    }
+}
+
 #[inline]
-pub fn simulate_game(state: &State) -> isize {
-    let mut test_state = state.clone();
+pub fn simulate_game<const N: usize>(state: &State<N>) -> isize {
+    simulate_game_with(state, &mut rand::thread_rng())
+}
+
+// Plays random moves until the game ends, drawing them from the given generator
+pub fn simulate_game_with<const N: usize>(state: &State<N>, rng: &mut impl Rng) -> isize {
+    playout(state, rng, |_| {})
+}
+
+// Plays random moves like `simulate_game_with` and appends every move played to `moves`
+pub fn simulate_game_recording<const N: usize>(
+    state: &State<N>,
+    rng: &mut impl Rng,
+    moves: &mut Vec<Action>,
+) -> isize {
+    playout(state, rng, |action| moves.push(action.clone()))
+}
+
+fn playout<const N: usize>(
+    state: &State<N>,
+    rng: &mut impl Rng,
+    mut record: impl FnMut(&Action),
+) -> isize {
+    let mut test_state = *state;
     let mut consecutive_skips = 0;
 
     // Maximum number of moves to prevent infinite loops
-    let max_iterations = 100;
+    let max_iterations = 2 * N * N;
     let mut iterations = 0;
 
     while test_state.remaining_moves > 0 && consecutive_skips < 2 && iterations < max_iterations {
@@ -419,8 +741,8 @@ pub fn simulate_game(state: &State) -> isize {
             current_action = None;
             consecutive_skips += 1;
         } else {
-            let mut rng = rand::thread_rng();
             let index = rng.gen_range(0..test_actions.len());
+            record(&test_actions[index]);
             current_action = Some(test_actions[index].clone());
             consecutive_skips = 0;
         }
@@ -439,11 +761,11 @@ pub fn simulate_game(state: &State) -> isize {
     }
 }
 
-pub fn caculate_win(state: State) -> Option<Color> {
+pub fn caculate_win<const N: usize>(state: State<N>) -> Option<Color> {
     let mut w_score: isize = 0;
     let mut b_score: isize = 0;
     for row in state.board.rows {
-        let (w, b) = row.count_colors();
+        let (w, b) = row.count_colors(N);
         w_score += w;
         b_score += b;
     }
@@ -454,67 +776,84 @@ pub fn caculate_win(state: State) -> Option<Color> {
     }
 }
 
-pub fn parse_state(json: serde_json::Value) -> State {
-    //todo!("Fix parse_state")
-    let mut new_board = Board::blank();
-    let mut moves_left: u8 = 0;
-    let next = match json["turn"] {
-        serde_json::Value::Bool(true) => Color::BLACK,
-        _ => Color::WHITE,
+// Reads a state from the game server's board JSON, e.g. {"board": [[-1, 0, ...], ...], "turn": true}
+// Cells are 1 for white, 0 for black and -1 for empty fields and "turn" is true when black is next
+// The optional "skipped" field tells whether the previous player passed
+pub fn parse_state<const N: usize>(json: &serde_json::Value) -> Result<State<N>, ParseError> {
+    parse_state_with(json, BoardOrientation::default())
+}
+
+// Reads a state like `parse_state` from a board laid out in the given orientation
+pub fn parse_state_with<const N: usize>(
+    json: &serde_json::Value,
+    orientation: BoardOrientation,
+) -> Result<State<N>, ParseError> {
+    let lines = json["board"]
+        .as_array()
+        .ok_or(ParseError::MissingField("board"))?;
+    if lines.len() != N {
+        return Err(ParseError::WrongSize {
+            expected: N,
+            found: lines.len(),
+        });
+    }
+    let next_turn = match json["turn"].as_bool() {
+        Some(true) => Color::BLACK,
+        Some(false) => Color::WHITE,
+        None => return Err(ParseError::MissingField("turn")),
     };
-    if let Some(board) = json["board"].as_array() {
-        for (x, row) in board.iter().enumerate() {
-            if let Some(row) = row.as_array() {
-                for (y, cell) in row.iter().enumerate() {
-                    match cell.as_i64() {
-                        Some(1) => {
-                            new_board.rows[y] = new_board.rows[y].set_pos(Color::WHITE, x).unwrap()
-                        }
-                        Some(0) => {
-                            new_board.rows[y] = new_board.rows[y].set_pos(Color::BLACK, x).unwrap()
-                        }
-                        Some(-1) => {
-                            moves_left += 1;
-                        }
-                        _ => {}
-                    }
-                }
-            }
+    let prev_player_skipped = match &json["skipped"] {
+        serde_json::Value::Null => false,
+        skipped => skipped
+            .as_bool()
+            .ok_or(ParseError::InvalidField("skipped"))?,
+    };
+    let mut new_board = Board::blank();
+    let mut empty_fields: usize = 0;
+    for (i, line) in lines.iter().enumerate() {
+        let cells = line.as_array().ok_or(ParseError::InvalidLine(i))?;
+        if cells.len() != N {
+            return Err(ParseError::WrongSize {
+                expected: N,
+                found: cells.len(),
+            });
         }
-    }
-    State {
-        board: new_board,
-        next_turn: next,
-        remaining_moves: moves_left,
-        prev_player_skipped: false,
-    }
-}
-
-pub fn print_state(state: State) {
-    println!("   0 1 2 3 4 5 6 7");
-    let black_comp = Color::BLACK.bitmask(); // << ((BOARD_SIZE - 1) * FIELD_SIZE);
-    let white_comp = Color::WHITE.bitmask(); // << ((BOARD_SIZE - 1) * FIELD_SIZE);
-    for (i, row) in state.board.into_iter().enumerate() {
-        print!("{i} ");
-        for f in 0..BOARD_SIZE {
-            let c = {
-                if row.value & (black_comp << (f * FIELD_SIZE)) != 0 {
-                    'B'
-                } else if row.value & (white_comp << (f * FIELD_SIZE)) != 0 {
-                    'W'
-                } else {
-                    '_'
+        for (j, cell) in cells.iter().enumerate() {
+            let position = match orientation {
+                BoardOrientation::Columns => Position::new(i, j),
+                BoardOrientation::Rows => Position::new(j, i),
+            };
+            let color = match cell.as_i64() {
+                Some(1) => Color::WHITE,
+                Some(0) => Color::BLACK,
+                Some(-1) => {
+                    empty_fields += 1;
+                    continue;
+                }
+                _ => {
+                    return Err(ParseError::InvalidCell {
+                        position,
+                        value: cell.to_string(),
+                    })
                 }
             };
-            print!("|{}", c);
+            new_board.rows[position.y] = new_board.rows[position.y]
+                .set_pos(color, position.x)
+                .expect("Each field is only set once");
         }
-        print!("|\n");
     }
-    let next = match state.next_turn {
-        Color::BLACK => "Black",
-        Color::WHITE => "White",
-    };
-    println!("Next: {}", next)
+    let remaining_moves =
+        u8::try_from(empty_fields).map_err(|_| ParseError::TooManyEmptyFields(empty_fields))?;
+    Ok(State {
+        board: new_board,
+        next_turn,
+        remaining_moves,
+        prev_player_skipped,
+    })
+}
+
+pub fn print_state<const N: usize>(state: State<N>) {
+    println!("{}", render_state(&state, &RenderOptions::default()));
 }
 
 #[cfg(test)]
@@ -523,12 +862,12 @@ mod othello_tests {
 
     #[test]
     fn test_board_empty_spaces() {
-        let board = Board::new();
+        let board: Board<8> = Board::new();
         assert_eq!(board.get_empty_positions().len(), 60);
     }
     #[test]
     fn test_row_get_pos() {
-        let board = Board::new();
+        let board: Board<8> = Board::new();
         assert_eq!(board.rows[3].get_pos(3), Some(Color::WHITE));
         assert_eq!(board.rows[3].get_pos(4), Some(Color::BLACK));
         assert_eq!(board.rows[4].get_pos(4), Some(Color::WHITE));
@@ -539,8 +878,228 @@ mod othello_tests {
     }
     #[test]
     fn test_row_set_pos() {
-        let board = Board::new();
+        let board: Board<8> = Board::new();
         assert!(board.rows[3].set_pos(Color::BLACK, 4).is_err());
         assert!(board.rows[3].set_pos(Color::WHITE, 3).is_err());
     }
+    #[test]
+    fn test_try_action_errors() {
+        let state: State = State::new();
+        assert_eq!(
+            state.try_action(&Action::new(Color::WHITE, Position::new(3, 2))),
+            Err(OthelloError::WrongColor(Color::BLACK))
+        );
+        assert_eq!(
+            state.try_action(&Action::new(Color::BLACK, Position::new(8, 2))),
+            Err(OthelloError::OutOfBounds(Position::new(8, 2)))
+        );
+        assert_eq!(
+            state.try_action(&Action::new(Color::BLACK, Position::new(3, 3))),
+            Err(OthelloError::Occupied(Position::new(3, 3)))
+        );
+        assert_eq!(
+            state.try_action(&Action::new(Color::BLACK, Position::new(0, 0))),
+            Err(OthelloError::NoFlips(Position::new(0, 0)))
+        );
+        assert!(!state.is_game_over());
+        let mut finished = state;
+        finished.remaining_moves = 0;
+        assert!(finished.is_game_over());
+        assert_eq!(
+            finished.try_action(&Action::new(Color::BLACK, Position::new(3, 2))),
+            Err(OthelloError::GameOver)
+        );
+    }
+    #[test]
+    fn test_try_action_legal_move() {
+        let state: State = State::new();
+        let new_state = state
+            .try_action(&Action::new(Color::BLACK, Position::new(3, 2)))
+            .unwrap();
+        assert_eq!(new_state.next_turn, Color::WHITE);
+        assert_eq!(new_state.remaining_moves, state.remaining_moves - 1);
+        assert_eq!(new_state.board.rows[2].get_pos(3), Some(Color::BLACK));
+        assert_eq!(new_state.board.rows[3].get_pos(3), Some(Color::BLACK));
+        assert_eq!(new_state.count_discs(Color::BLACK), 4);
+        assert_eq!(new_state.count_discs(Color::WHITE), 1);
+    }
+    #[test]
+    fn test_small_and_large_boards() {
+        let small: State<6> = State::new();
+        assert_eq!(small.board.get_empty_positions().len(), 32);
+        assert_eq!(small.get_actions().len(), 4);
+        let large: State<10> = State::new();
+        assert_eq!(large.board.get_empty_positions().len(), 96);
+        assert_eq!(large.get_actions().len(), 4);
+        assert_eq!(
+            large.try_action(&Action::new(Color::BLACK, Position::new(10, 4))),
+            Err(OthelloError::OutOfBounds(Position::new(10, 4)))
+        );
+    }
+    #[test]
+    fn test_small_board_game_fills_board() {
+        let mut state: State<6> = State::new();
+        while state.remaining_moves > 0 {
+            let action = state.get_actions().first().cloned();
+            state = state.do_action(action);
+        }
+        let (w, b) = state
+            .board
+            .into_iter()
+            .map(|row| row.count_colors(6))
+            .fold((0, 0), |acc, c| (acc.0 + c.0, acc.1 + c.1));
+        assert!(w + b <= 36);
+        assert!(state.board.get_empty_positions().len() as isize == 36 - w - b);
+    }
+    #[test]
+    fn test_position_encoding_round_trip() {
+        let state: State = State::new()
+            .try_action(&Action::new(Color::BLACK, Position::new(3, 2)))
+            .unwrap();
+        let encoded = state.to_string();
+        assert_eq!(
+            encoded,
+            concat!(
+                "--------", "--------", "---X----", "---XX---", "---XO---", "--------", "--------",
+                "--------", " O"
+            )
+        );
+        let parsed: State = encoded.parse().unwrap();
+        assert_eq!(parsed, state);
+    }
+    #[test]
+    fn test_position_parse_errors() {
+        assert_eq!(
+            "XO-".parse::<State<6>>(),
+            Err(PositionError::WrongLength {
+                expected: 36,
+                found: 3
+            })
+        );
+        let bad_cell = format!("{}?", "-".repeat(35));
+        assert_eq!(
+            bad_cell.parse::<State<6>>(),
+            Err(PositionError::InvalidCell('?'))
+        );
+        let bad_turn = format!("{} ?", "-".repeat(36));
+        assert_eq!(
+            bad_turn.parse::<State<6>>(),
+            Err(PositionError::InvalidTurn('?'))
+        );
+        // The largest board can't be entirely empty, one disc less fits
+        assert_eq!(
+            "-".repeat(256).parse::<State<16>>(),
+            Err(PositionError::TooManyEmptyFields(256))
+        );
+        let one_disc = format!("X{}", "-".repeat(255));
+        assert_eq!(one_disc.parse::<State<16>>().unwrap().remaining_moves, 255);
+    }
+    #[test]
+    fn test_parse_server_boards() {
+        let opening = serde_json::from_str(include_str!("../tests/fixtures/board_opening.json"));
+        let state: State = parse_state(&opening.unwrap()).unwrap();
+        assert_eq!(state, State::new());
+
+        // The board served after the moves below, white is to move after black's (2, 3)
+        let json: serde_json::Value =
+            serde_json::from_str(include_str!("../tests/fixtures/board_midgame.json")).unwrap();
+        let moves = [
+            (Color::BLACK, 5, 4),
+            (Color::WHITE, 3, 5),
+            (Color::BLACK, 2, 2),
+            (Color::WHITE, 3, 2),
+            (Color::BLACK, 2, 3),
+        ];
+        let mut played: State = State::new();
+        for (color, x, y) in moves {
+            played = played
+                .try_action(&Action::new(color, Position::new(x, y)))
+                .unwrap();
+        }
+        let state: State = parse_state(&json).unwrap();
+        assert_eq!(state, played);
+        assert_eq!(state.disc_at(Position::new(2, 3)), Some(Color::BLACK));
+        assert_eq!(state.disc_at(Position::new(3, 2)), Some(Color::WHITE));
+        assert_eq!(state.next_turn, Color::WHITE);
+        assert_eq!(state.remaining_moves, 55);
+        assert!(!state.prev_player_skipped);
+        // Reading the lines as rows transposes the board
+        let transposed: State = parse_state_with(&json, BoardOrientation::Rows).unwrap();
+        assert_eq!(transposed.disc_at(Position::new(3, 2)), Some(Color::BLACK));
+        assert_eq!(transposed.disc_at(Position::new(2, 3)), Some(Color::WHITE));
+        assert_eq!(transposed.disc_at(Position::new(4, 5)), Some(Color::BLACK));
+    }
+    #[test]
+    fn test_parse_skipped_field() {
+        // The upstream server never sends "skipped", other servers may tell about a pass with it
+        let json: serde_json::Value =
+            serde_json::from_str(include_str!("../tests/fixtures/board_midgame.json")).unwrap();
+        for (skipped, prev_player_skipped) in [(true, true), (false, false)] {
+            let board =
+                serde_json::json!({"board": json["board"], "turn": true, "skipped": skipped});
+            let state: State = parse_state(&board).unwrap();
+            assert_eq!(state.next_turn, Color::BLACK);
+            assert_eq!(state.prev_player_skipped, prev_player_skipped);
+        }
+    }
+    #[test]
+    fn test_parse_server_board_errors() {
+        let row = serde_json::json!([-1, -1, -1, -1, -1, -1]);
+        let board = serde_json::json!([row, row, row, row, row, row]);
+        let parse = |json: serde_json::Value| parse_state::<6>(&json);
+        assert!(parse(serde_json::json!({"board": board, "turn": true})).is_ok());
+        assert_eq!(
+            parse(serde_json::json!({"turn": true})),
+            Err(ParseError::MissingField("board"))
+        );
+        assert_eq!(
+            parse(serde_json::json!({"board": board, "turn": "black"})),
+            Err(ParseError::MissingField("turn"))
+        );
+        assert_eq!(
+            parse(serde_json::json!({"board": board, "turn": true, "skipped": 1})),
+            Err(ParseError::InvalidField("skipped"))
+        );
+        assert_eq!(
+            parse(serde_json::json!({"board": [row, row], "turn": true})),
+            Err(ParseError::WrongSize {
+                expected: 6,
+                found: 2
+            })
+        );
+        assert_eq!(
+            parse(serde_json::json!({"board": [row, row, row, row, row, [-1]], "turn": true})),
+            Err(ParseError::WrongSize {
+                expected: 6,
+                found: 1
+            })
+        );
+        assert_eq!(
+            parse(serde_json::json!({"board": [row, row, row, row, row, 0], "turn": true})),
+            Err(ParseError::InvalidLine(5))
+        );
+        let bad_line = serde_json::json!([-1, -1, 2, -1, -1, -1]);
+        assert_eq!(
+            parse(serde_json::json!({"board": [row, bad_line, row, row, row, row], "turn": true})),
+            Err(ParseError::InvalidCell {
+                position: Position::new(1, 2),
+                value: "2".to_string()
+            })
+        );
+        let empty = serde_json::json!({"board": vec![vec![-1; 16]; 16], "turn": true});
+        assert_eq!(
+            parse_state::<16>(&empty),
+            Err(ParseError::TooManyEmptyFields(256))
+        );
+    }
+    #[test]
+    fn test_handicap() {
+        let state: State = starting_state(None, Some("white:2")).unwrap();
+        assert_eq!(state.board.rows[0].get_pos(0), Some(Color::WHITE));
+        assert_eq!(state.board.rows[7].get_pos(7), Some(Color::WHITE));
+        assert_eq!(state.board.rows[0].get_pos(7), None);
+        assert_eq!(state.remaining_moves, 58);
+        assert!(starting_state::<8>(None, Some("white:5")).is_err());
+        assert!(starting_state::<8>(None, Some("purple:1")).is_err());
+    }
 }
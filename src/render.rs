@@ -0,0 +1,171 @@
+use crate::othello::{Color, Position, State};
+use std::fmt::Write;
+
+const ANSI_RESET: &str = "\x1b[0m";
+const ANSI_BOARD: &str = "\x1b[42m";
+const ANSI_LAST_MOVE: &str = "\x1b[41m";
+const ANSI_FLIPPED: &str = "\x1b[43m";
+const ANSI_BLACK: &str = "\x1b[1;30m";
+const ANSI_WHITE: &str = "\x1b[1;97m";
+const ANSI_LEGAL: &str = "\x1b[93m";
+
+// Controls what `render_state` adds to the board, the default renders only the discs and the side to move
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RenderOptions {
+    // Mark the legal moves of the side to move with '*'
+    pub legal_moves: bool,
+    // The position of the last move played
+    pub last_move: Option<Position>,
+    // The discs flipped by the last move
+    pub flipped: Vec<Position>,
+    // Add the number of discs for each side
+    pub score: bool,
+    // Use ANSI escape codes to color the board and highlight the last move and flipped discs
+    pub colors: bool,
+}
+impl RenderOptions {
+    // Options highlighting the move that led from `before` to `after`
+    pub fn for_move<const N: usize>(before: &State<N>, after: &State<N>) -> RenderOptions {
+        let last_move = (0..N)
+            .flat_map(|y| (0..N).map(move |x| Position::new(x, y)))
+            .find(|pos| before.disc_at(*pos).is_none() && after.disc_at(*pos).is_some());
+        RenderOptions {
+            last_move,
+            flipped: flipped_discs(before, after),
+            ..RenderOptions::default()
+        }
+    }
+}
+
+// Returns the positions of the discs that changed color between the two states
+pub fn flipped_discs<const N: usize>(before: &State<N>, after: &State<N>) -> Vec<Position> {
+    (0..N)
+        .flat_map(|y| (0..N).map(move |x| Position::new(x, y)))
+        .filter(|pos| {
+            matches!(
+                (before.disc_at(*pos), after.disc_at(*pos)),
+                (Some(old), Some(new)) if old != new
+            )
+        })
+        .collect()
+}
+
+// Renders the board as text, one row per line with column and row indices
+// Positions in the last move and flipped lines are written as "y,x" like console input
+pub fn render_state<const N: usize>(state: &State<N>, options: &RenderOptions) -> String {
+    let legal: Vec<Position> = match options.legal_moves {
+        true => state.get_actions().iter().map(|a| a.position).collect(),
+        false => Vec::new(),
+    };
+    let mut out = String::new();
+    let header: Vec<String> = (0..N).map(|i| i.to_string()).collect();
+    let _ = writeln!(out, "   {}", header.join(" "));
+    for y in 0..N {
+        let _ = write!(out, "{y} ");
+        for x in 0..N {
+            let pos = Position::new(x, y);
+            let c = match state.disc_at(pos) {
+                Some(Color::BLACK) => 'B',
+                Some(Color::WHITE) => 'W',
+                None if legal.contains(&pos) => '*',
+                None => '_',
+            };
+            if options.colors {
+                let background = if Some(pos) == options.last_move {
+                    ANSI_LAST_MOVE
+                } else if options.flipped.contains(&pos) {
+                    ANSI_FLIPPED
+                } else {
+                    ANSI_BOARD
+                };
+                let foreground = match c {
+                    'B' => ANSI_BLACK,
+                    'W' => ANSI_WHITE,
+                    _ => ANSI_LEGAL,
+                };
+                let _ = write!(out, "{ANSI_BOARD}|{background}{foreground}{c}{ANSI_RESET}");
+            } else {
+                let _ = write!(out, "|{c}");
+            }
+        }
+        match options.colors {
+            true => writeln!(out, "{ANSI_BOARD}|{ANSI_RESET}"),
+            false => writeln!(out, "|"),
+        }
+        .expect("Writing to a String can't fail");
+    }
+    let format_pos = |pos: &Position| format!("{},{}", pos.y, pos.x);
+    if let Some(last_move) = &options.last_move {
+        let _ = writeln!(out, "Last move: {}", format_pos(last_move));
+    }
+    if !options.flipped.is_empty() {
+        let flipped: Vec<String> = options.flipped.iter().map(format_pos).collect();
+        let _ = writeln!(out, "Flipped: {}", flipped.join(" "));
+    }
+    if options.score {
+        let _ = writeln!(
+            out,
+            "Black: {} White: {}",
+            state.count_discs(Color::BLACK),
+            state.count_discs(Color::WHITE)
+        );
+    }
+    let next = match state.next_turn {
+        Color::BLACK => "Black",
+        Color::WHITE => "White",
+    };
+    let _ = write!(out, "Next: {}", next);
+    out
+}
+
+#[cfg(test)]
+mod render_tests {
+    use super::*;
+    use crate::othello::Action;
+
+    #[test]
+    fn test_render_default() {
+        let state: State = State::new();
+        let expected = concat!(
+            "   0 1 2 3 4 5 6 7\n",
+            "0 |_|_|_|_|_|_|_|_|\n",
+            "1 |_|_|_|_|_|_|_|_|\n",
+            "2 |_|_|_|_|_|_|_|_|\n",
+            "3 |_|_|_|W|B|_|_|_|\n",
+            "4 |_|_|_|B|W|_|_|_|\n",
+            "5 |_|_|_|_|_|_|_|_|\n",
+            "6 |_|_|_|_|_|_|_|_|\n",
+            "7 |_|_|_|_|_|_|_|_|\n",
+            "Next: Black"
+        );
+        assert_eq!(render_state(&state, &RenderOptions::default()), expected);
+    }
+    #[test]
+    fn test_render_move_details() {
+        let before: State<6> = State::new();
+        let action = Action::new(Color::BLACK, Position::new(2, 1));
+        let after = before.try_action(&action).unwrap();
+        let options = RenderOptions {
+            legal_moves: true,
+            score: true,
+            ..RenderOptions::for_move(&before, &after)
+        };
+        let expected = concat!(
+            "   0 1 2 3 4 5\n",
+            "0 |_|_|_|_|_|_|\n",
+            "1 |_|*|B|*|_|_|\n",
+            "2 |_|_|B|B|_|_|\n",
+            "3 |_|*|B|W|_|_|\n",
+            "4 |_|_|_|_|_|_|\n",
+            "5 |_|_|_|_|_|_|\n",
+            "Last move: 1,2\n",
+            "Flipped: 2,2\n",
+            "Black: 4 White: 1\n",
+            "Next: White"
+        );
+        let rendered = render_state(&after, &options);
+        assert_eq!(rendered, expected, "\n{rendered}");
+        assert_eq!(options.last_move, Some(action.position));
+        assert_eq!(options.flipped, vec![Position::new(2, 2)]);
+    }
+}
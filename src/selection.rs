@@ -0,0 +1,273 @@
+use crate::othello::{Action, State};
+
+// Weight of the prior in the progressive bias term, fading with the visits of the move
+pub const PROGRESSIVE_BIAS_WEIGHT: f32 = 1.0;
+// Visits of a move at which its own results and its RAVE results are weighed about equally
+pub const DEFAULT_RAVE_EQUIVALENCE: f32 = 1000.0;
+
+// How the search picks the child to descend into, all policies add an exploration bonus
+// scaled by the exploration constant to the mean value of the move
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SelectionPolicy {
+    // Upper Confidence Bound, the bonus shrinks with the visits of the move
+    #[default]
+    Ucb1,
+    // UCB1 with the bonus bounded by the observed variance of the move's results
+    Ucb1Tuned,
+    // Predictor + UCB as used by AlphaZero, the bonus is shared out by the move priors
+    Puct,
+    // UCB1 with an extra bonus from the move's prior that fades as the move is visited
+    ProgressiveBias,
+}
+impl SelectionPolicy {
+    // Policies that need priors for the moves, they also expand the moves by their priors
+    pub fn uses_priors(&self) -> bool {
+        matches!(
+            self,
+            SelectionPolicy::Puct | SelectionPolicy::ProgressiveBias
+        )
+    }
+
+    // Scores a visited move, the child with the highest score is selected
+    pub fn score(&self, stats: &ChildStats, parent_visits: usize, explore: f32) -> f32 {
+        let visits = stats.visits as f32;
+        let mean = stats.mean();
+        let log_parent = (parent_visits as f32).ln();
+        match self {
+            SelectionPolicy::Ucb1 => mean + explore * (2.0 * log_parent / visits).sqrt(),
+            SelectionPolicy::Ucb1Tuned => {
+                // The variance bound is taken on the win rate in [0, 1], then scaled to [-1, 1]
+                let win_rate = (stats.score / visits + 1.0) / 2.0;
+                let squares = (stats.score_squares + 2.0 * stats.score + visits) / (4.0 * visits);
+                let variance =
+                    (squares - win_rate * win_rate).max(0.0) + (2.0 * log_parent / visits).sqrt();
+                mean + explore * 2.0 * (log_parent / visits * variance.min(0.25)).sqrt()
+            }
+            SelectionPolicy::Puct => {
+                mean + explore * stats.prior * (parent_visits as f32).sqrt() / (1.0 + visits)
+            }
+            SelectionPolicy::ProgressiveBias => {
+                mean + explore * (2.0 * log_parent / visits).sqrt()
+                    + PROGRESSIVE_BIAS_WEIGHT * stats.prior / (1.0 + visits)
+            }
+        }
+    }
+
+    // Scores a move that hasn't been expanded yet, which the policies using priors weigh
+    // against the visited moves so a node doesn't have to be fully expanded to be searched
+    // Its mean value is taken as 0 and it counts as visited once for both bonuses of progressive bias
+    pub fn unvisited_score(&self, prior: f32, parent_visits: usize, explore: f32) -> f32 {
+        match self {
+            SelectionPolicy::Ucb1 | SelectionPolicy::Ucb1Tuned => f32::INFINITY,
+            SelectionPolicy::Puct => explore * prior * (parent_visits as f32).sqrt(),
+            SelectionPolicy::ProgressiveBias => {
+                explore * (2.0 * (parent_visits as f32).ln()).sqrt()
+                    + PROGRESSIVE_BIAS_WEIGHT * prior / 2.0
+            }
+        }
+    }
+}
+
+// The statistics of a move used by the selection
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChildStats {
+    pub visits: usize,
+    // Sum of the results of the move's games, from the perspective of the player making it
+    pub score: f32,
+    // Sum of the squared results, for the variance used by UCB1-Tuned
+    pub score_squares: f32,
+    // Share of the prior among the moves of the parent, in [0, 1]
+    pub prior: f32,
+    // All-moves-as-first statistics, the results of the games below the parent in which
+    // the player made the move at any point, and the weight they get in the mean value
+    pub rave_visits: usize,
+    pub rave_score: f32,
+    pub rave_weight: f32,
+}
+impl ChildStats {
+    // The mean result of the move, blended with its RAVE results by their weight
+    pub fn mean(&self) -> f32 {
+        let mean = self.score / self.visits as f32;
+        if self.rave_visits == 0 {
+            return mean;
+        }
+        let rave_mean = self.rave_score / self.rave_visits as f32;
+        (1.0 - self.rave_weight) * mean + self.rave_weight * rave_mean
+    }
+
+    // The weight of the RAVE results after the given visits of the move, starting at 1
+    // and decaying so that both results count about the same at the equivalence visits
+    pub fn rave_weight(visits: usize, equivalence: f32) -> f32 {
+        (equivalence / (3.0 * visits as f32 + equivalence)).sqrt()
+    }
+}
+
+// Supplies prior weights for the moves of a state, e.g. from Othello knowledge or a learned policy
+// The weights are normalized over the moves by the search, so they only need to be relative
+pub trait MovePrior<const N: usize>: Send {
+    fn priors(&self, state: &State<N>, actions: &[Action]) -> Vec<f32>;
+}
+// Any function from the state and its moves to their weights is a prior
+impl<const N: usize, F> MovePrior<N> for F
+where
+    F: Fn(&State<N>, &[Action]) -> Vec<f32> + Send,
+{
+    fn priors(&self, state: &State<N>, actions: &[Action]) -> Vec<f32> {
+        self(state, actions)
+    }
+}
+
+// Gives every move the same weight
+#[derive(Debug, Default, Clone, Copy)]
+pub struct UniformPrior;
+impl<const N: usize> MovePrior<N> for UniformPrior {
+    fn priors(&self, _state: &State<N>, actions: &[Action]) -> Vec<f32> {
+        vec![1.0; actions.len()]
+    }
+}
+
+// Prefers corners and edges and avoids the fields next to the corners, which give them away
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CornerPrior;
+impl CornerPrior {
+    fn weight<const N: usize>(action: &Action) -> f32 {
+        // Distances from the nearest column and row edges, corners are on both of them
+        let edge_distance = |c: usize| c.min(N - 1 - c);
+        let (dx, dy) = (
+            edge_distance(action.position.x),
+            edge_distance(action.position.y),
+        );
+        match (dx.min(dy), dx.max(dy)) {
+            (0, 0) => 8.0,
+            (1, 1) => 0.25,
+            (0, 1) => 0.5,
+            (0, _) => 2.0,
+            _ => 1.0,
+        }
+    }
+}
+impl<const N: usize> MovePrior<N> for CornerPrior {
+    fn priors(&self, _state: &State<N>, actions: &[Action]) -> Vec<f32> {
+        actions.iter().map(Self::weight::<N>).collect()
+    }
+}
+
+// Normalizes the prior weights of the moves to sum up to 1, uniform if they are all 0
+pub fn normalize(weights: &mut [f32]) {
+    let total: f32 = weights.iter().map(|w| w.max(0.0)).sum();
+    let moves = weights.len() as f32;
+    for weight in weights.iter_mut() {
+        *weight = match total > 0.0 {
+            true => weight.max(0.0) / total,
+            false => 1.0 / moves,
+        };
+    }
+}
+
+#[cfg(test)]
+mod selection_tests {
+    use super::*;
+    use crate::othello::{Color, Position};
+
+    fn stats(visits: usize, score: isize, score_squares: usize, prior: f32) -> ChildStats {
+        ChildStats {
+            visits,
+            score: score as f32,
+            score_squares: score_squares as f32,
+            prior,
+            rave_visits: 0,
+            rave_score: 0.0,
+            rave_weight: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_policies_prefer_better_moves() {
+        for policy in [
+            SelectionPolicy::Ucb1,
+            SelectionPolicy::Ucb1Tuned,
+            SelectionPolicy::Puct,
+            SelectionPolicy::ProgressiveBias,
+        ] {
+            let good = policy.score(&stats(50, 30, 40, 0.5), 100, 1.0);
+            let bad = policy.score(&stats(50, -30, 40, 0.5), 100, 1.0);
+            assert!(good > bad, "{policy:?}");
+            // Less visited moves get a larger bonus
+            let rare = policy.score(&stats(5, 3, 4, 0.5), 100, 1.0);
+            let common = policy.score(&stats(50, 30, 40, 0.5), 100, 1.0);
+            assert!(rare > common, "{policy:?}");
+        }
+        // Priors only change the policies using them
+        let likely = stats(10, 0, 10, 0.9);
+        let unlikely = stats(10, 0, 10, 0.1);
+        for policy in [SelectionPolicy::Puct, SelectionPolicy::ProgressiveBias] {
+            assert!(policy.score(&likely, 20, 1.0) > policy.score(&unlikely, 20, 1.0));
+        }
+        for policy in [SelectionPolicy::Ucb1, SelectionPolicy::Ucb1Tuned] {
+            assert_eq!(
+                policy.score(&likely, 20, 1.0),
+                policy.score(&unlikely, 20, 1.0)
+            );
+        }
+        // Moves with consistent results explore less under UCB1-Tuned
+        let steady = SelectionPolicy::Ucb1Tuned.score(&stats(10_000, 0, 0, 0.0), 100_000, 1.0);
+        let varied = SelectionPolicy::Ucb1Tuned.score(&stats(10_000, 0, 10_000, 0.0), 100_000, 1.0);
+        assert!(steady < varied);
+    }
+    #[test]
+    fn test_unvisited_moves() {
+        // Policies without priors expand every move before searching deeper
+        for policy in [SelectionPolicy::Ucb1, SelectionPolicy::Ucb1Tuned] {
+            assert_eq!(policy.unvisited_score(0.0, 100, 1.0), f32::INFINITY);
+        }
+        // The others weigh them by their prior against the visited moves
+        for policy in [SelectionPolicy::Puct, SelectionPolicy::ProgressiveBias] {
+            assert!(policy.unvisited_score(0.01, 100, 1.0) < policy.unvisited_score(0.9, 100, 1.0));
+            let weak = policy.score(&stats(90, -60, 90, 0.5), 100, 1.0);
+            assert!(policy.unvisited_score(0.3, 100, 1.0) > weak, "{policy:?}");
+        }
+        // Under progressive bias an unvisited move scores like a move visited once with a draw
+        let drawn = SelectionPolicy::ProgressiveBias.score(&stats(1, 0, 0, 0.3), 100, 1.0);
+        assert_eq!(
+            SelectionPolicy::ProgressiveBias.unvisited_score(0.3, 100, 1.0),
+            drawn
+        );
+        // PUCT keeps searching a good move before trying an unlikely one
+        let good = SelectionPolicy::Puct.score(&stats(90, 60, 90, 0.5), 100, 1.0);
+        assert!(SelectionPolicy::Puct.unvisited_score(0.01, 100, 1.0) < good);
+    }
+    #[test]
+    fn test_rave_mean() {
+        let mut move_stats = stats(10, -10, 10, 0.0);
+        assert_eq!(move_stats.mean(), -1.0);
+        move_stats.rave_visits = 100;
+        move_stats.rave_score = 100.0;
+        move_stats.rave_weight = ChildStats::rave_weight(10, DEFAULT_RAVE_EQUIVALENCE);
+        assert!(move_stats.mean() > 0.0);
+        // The weight decays towards the move's own results
+        assert_eq!(ChildStats::rave_weight(0, DEFAULT_RAVE_EQUIVALENCE), 1.0);
+        assert_eq!(ChildStats::rave_weight(1000, 1000.0), 0.5);
+        assert!(ChildStats::rave_weight(100_000, DEFAULT_RAVE_EQUIVALENCE) < 0.1);
+    }
+    #[test]
+    fn test_corner_prior() {
+        let state: State = State::new();
+        let action = |x, y| Action::new(Color::BLACK, Position::new(x, y));
+        let actions = [
+            action(0, 0),
+            action(1, 1),
+            action(0, 1),
+            action(3, 0),
+            action(3, 3),
+        ];
+        let mut priors = CornerPrior.priors(&state, &actions);
+        normalize(&mut priors);
+        assert!((priors.iter().sum::<f32>() - 1.0).abs() < 1e-6);
+        assert!(priors[0] > priors[3] && priors[3] > priors[4]);
+        assert!(priors[4] > priors[2] && priors[2] > priors[1]);
+
+        let mut zeros = vec![0.0; 4];
+        normalize(&mut zeros);
+        assert_eq!(zeros, vec![0.25; 4]);
+    }
+}
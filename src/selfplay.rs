@@ -0,0 +1,243 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use crate::mcts::{SearchReport, MCTS};
+use crate::othello::{caculate_win, starting_state, Color, State};
+use crate::selection::{CornerPrior, SelectionPolicy, DEFAULT_RAVE_EQUIVALENCE};
+use crate::training::{Sample, TrainingData};
+
+// The search of one side in self-play
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlayerSettings {
+    pub explore: f32,
+    // The policies using priors weigh the moves with `CornerPrior`
+    pub selection: SelectionPolicy,
+    pub rave: bool,
+}
+impl Default for PlayerSettings {
+    fn default() -> Self {
+        PlayerSettings {
+            explore: 1.0,
+            selection: SelectionPolicy::Ucb1,
+            rave: false,
+        }
+    }
+}
+impl PlayerSettings {
+    pub fn mcts<const N: usize>(&self, color: Color) -> MCTS<N> {
+        let mut mcts = MCTS::new(color, self.explore).with_selection(self.selection);
+        if self.selection.uses_priors() {
+            mcts = mcts.with_prior(CornerPrior);
+        }
+        match self.rave {
+            true => mcts.with_rave(DEFAULT_RAVE_EQUIVALENCE),
+            false => mcts,
+        }
+    }
+}
+
+// The games between two AIs and where their training data goes
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelfplayConfig {
+    pub games: usize,
+    // Search iterations for the first move, growing by 1% every round
+    pub iterations: usize,
+    pub black: PlayerSettings,
+    pub white: PlayerSettings,
+    // Search for both sides in one tree with black's settings
+    pub shared_tree: bool,
+    // Board size, 6, 8 or 10
+    pub size: usize,
+    // Games played at the same time
+    pub threads: usize,
+    // Seed of the first game, every following game uses the next seed
+    pub seed: Option<u64>,
+    // Appends the positions of the games as training data, skipping the games already in it
+    pub output: Option<PathBuf>,
+    // Writes every position in all rotations and reflections of the board
+    pub symmetries: bool,
+    // Starting position and handicap as accepted by `starting_state`
+    pub position: Option<String>,
+    pub handicap: Option<String>,
+}
+impl Default for SelfplayConfig {
+    fn default() -> Self {
+        SelfplayConfig {
+            games: 1,
+            iterations: 500,
+            black: PlayerSettings::default(),
+            white: PlayerSettings::default(),
+            shared_tree: false,
+            size: 8,
+            threads: 1,
+            seed: None,
+            output: None,
+            symmetries: false,
+            position: None,
+            handicap: None,
+        }
+    }
+}
+
+// Results of the games played so far, a positive balance means white won more games
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SelfplayResults {
+    pub black_wins: usize,
+    pub white_wins: usize,
+    pub draws: usize,
+    // Games skipped because the output file already has them
+    pub skipped: usize,
+}
+impl SelfplayResults {
+    pub fn win_balance(&self) -> isize {
+        self.white_wins as isize - self.black_wins as isize
+    }
+
+    fn add(&mut self, winner: Option<Color>) {
+        match winner {
+            Some(Color::BLACK) => self.black_wins += 1,
+            Some(Color::WHITE) => self.white_wins += 1,
+            None => self.draws += 1,
+        }
+    }
+}
+
+// Plays the games on the configured board size, `on_game` gets the number and winner of every
+// game as soon as it is finished
+pub fn selfplay(
+    config: &SelfplayConfig,
+    on_game: impl Fn(usize, Option<Color>) + Sync,
+) -> Result<SelfplayResults, String> {
+    match config.size {
+        6 => play_games::<6>(config, on_game),
+        8 => play_games::<8>(config, on_game),
+        10 => play_games::<10>(config, on_game),
+        size => Err(format!("Unsupported board size {size}, use 6, 8 or 10")),
+    }
+}
+
+// Every thread takes the next game until all of them are played
+// With an output file the games already written to it are skipped and the new ones appended
+fn play_games<const N: usize>(
+    config: &SelfplayConfig,
+    on_game: impl Fn(usize, Option<Color>) + Sync,
+) -> Result<SelfplayResults, String> {
+    let start: State<N> = starting_state(config.position.as_deref(), config.handicap.as_deref())
+        .map_err(|e| format!("Invalid starting position: {e}"))?;
+    let (data, finished) = match &config.output {
+        Some(path) => {
+            let (data, finished) = TrainingData::open(path, config.symmetries)?;
+            (Some(Mutex::new(data)), finished)
+        }
+        None => (None, Default::default()),
+    };
+    let next_game = AtomicUsize::new(0);
+    let results = Mutex::new(SelfplayResults {
+        skipped: finished.iter().filter(|&&game| game < config.games).count(),
+        ..SelfplayResults::default()
+    });
+    std::thread::scope(|scope| {
+        let threads: Vec<_> = (0..config.threads.min(config.games))
+            .map(|_| {
+                scope.spawn(|| loop {
+                    let game = next_game.fetch_add(1, Ordering::Relaxed);
+                    if game >= config.games {
+                        return Ok::<(), String>(());
+                    }
+                    if finished.contains(&game) {
+                        continue;
+                    }
+                    let seed = config.seed.map(|seed| seed.wrapping_add(game as u64));
+                    let mut samples = Vec::new();
+                    let winner = play_game(start, config, seed, |state, report| {
+                        if data.is_some() {
+                            samples.extend(Sample::new(*state, report));
+                        }
+                    });
+                    if let Some(data) = &data {
+                        data.lock()
+                            .expect("No game panics")
+                            .write_game(game, &samples, winner)?;
+                    }
+                    on_game(game, winner);
+                    results.lock().expect("No game panics").add(winner);
+                })
+            })
+            .collect();
+        threads
+            .into_iter()
+            .try_for_each(|thread| thread.join().expect("No game panics"))
+    })?;
+    Ok(results.into_inner().expect("No game panics"))
+}
+
+// Plays a single game, both AIs start with the same number of iterations
+// which grows by 1% every round to balance the game simulations being shorter
+// With a shared tree black's tree searches the moves of both sides
+// Every searched state is passed to `record` with the report of its search
+pub fn play_game<const N: usize>(
+    start: State<N>,
+    config: &SelfplayConfig,
+    seed: Option<u64>,
+    mut record: impl FnMut(&State<N>, &SearchReport),
+) -> Option<Color> {
+    let mut black = config.black.mcts(Color::BLACK);
+    let mut white = config.white.mcts(Color::WHITE);
+    if let Some(seed) = seed {
+        // The two sides draw different random moves from the game's seed
+        black = black.with_seed(seed.wrapping_mul(2));
+        white = white.with_seed(seed.wrapping_mul(2).wrapping_add(1));
+    }
+    let mut state = start;
+    let mut iterations = config.iterations;
+    while !state.is_game_over() {
+        let mcts = match state.next_turn {
+            Color::WHITE if !config.shared_tree => &mut white,
+            _ => &mut black,
+        };
+        let (action, report) = mcts.search_with_report(state, iterations, |_, _, _| {});
+        record(&state, &report);
+        state = state.do_action(action.ok());
+        if state.next_turn == start.next_turn {
+            iterations += iterations / 100;
+        }
+    }
+    caculate_win(state)
+}
+
+#[cfg(test)]
+mod selfplay_tests {
+    use super::*;
+
+    #[test]
+    fn test_seeded_games() {
+        let config = SelfplayConfig {
+            games: 2,
+            iterations: 20,
+            size: 6,
+            threads: 2,
+            seed: Some(1),
+            ..SelfplayConfig::default()
+        };
+        let played = Mutex::new(Vec::new());
+        let results = selfplay(&config, |game, winner| {
+            played.lock().unwrap().push((game, winner));
+        })
+        .unwrap();
+        let mut played = played.into_inner().unwrap();
+        played.sort_by_key(|&(game, _)| game);
+        assert_eq!(results.black_wins + results.white_wins + results.draws, 2);
+        // Every game is repeated by its seed
+        let start: State<6> = State::new();
+        for (game, winner) in played {
+            assert_eq!(
+                play_game(start, &config, Some(1 + game as u64), |_, _| {}),
+                winner
+            );
+        }
+
+        let seven = SelfplayConfig { size: 7, ..config };
+        assert!(selfplay(&seven, |_, _| {}).is_err());
+    }
+}
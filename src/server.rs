@@ -0,0 +1,440 @@
+use crate::client::protocol_color;
+use crate::othello::{starting_state, Action, Color, Position, State};
+use rand::Rng;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use tiny_http::{Method, Request, Response, Server};
+
+// The session used by requests without a "/games/{id}" prefix
+pub const DEFAULT_SESSION: &str = "default";
+// How long "/waitTurn" requests are held when they don't give a timeout
+const DEFAULT_WAIT_TIMEOUT: Duration = Duration::from_secs(30);
+// How often held requests are checked while no other requests arrive
+const WAIT_CHECK_INTERVAL: Duration = Duration::from_millis(50);
+
+// A single game hosted by the server
+// If an opponent color is given the server plays that side with random moves
+#[derive(Debug)]
+struct Session {
+    state: State,
+    opponent: Option<Color>,
+}
+
+#[derive(Debug, Default)]
+struct Sessions {
+    games: HashMap<String, Session>,
+    next_id: usize,
+    waiting: Vec<WaitingRequest>,
+}
+
+// A "/waitTurn" request held until it is the player's turn or its deadline passes
+#[derive(Debug)]
+struct WaitingRequest {
+    request: Request,
+    session: String,
+    color: Color,
+    deadline: Instant,
+}
+
+// How the server answers a request to a session
+enum Reply {
+    Body(String),
+    // Hold the request until it is the color's turn or the timeout passes
+    WaitForTurn(Color, Duration),
+}
+
+// Hosts Othello games over HTTP using the endpoints of the upstream game server
+// Each game is a session reached under "/games/{id}", the default session also
+// answers the endpoints without a prefix so clients of the upstream server work unchanged
+// "POST /games" opens a new session, optionally taking "opponent", "position" and
+// "handicap" query parameters, and answers with its ID
+// On top of the upstream endpoints "/waitTurn/{player}" answers like "/turn" once it is the
+// player's turn, or after the number of seconds in its optional "timeout" query parameter
+pub struct GameServer {
+    server: Arc<Server>,
+    sessions: Arc<Mutex<Sessions>>,
+    stopped: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl GameServer {
+    // Starts serving on the given address, use port 0 to pick any free port
+    pub fn start(addr: &str) -> std::io::Result<GameServer> {
+        let server = Arc::new(Server::http(addr).map_err(std::io::Error::other)?);
+        let sessions = Arc::new(Mutex::new(Sessions::default()));
+        let stopped = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let server = Arc::clone(&server);
+            let sessions = Arc::clone(&sessions);
+            let stopped = Arc::clone(&stopped);
+            std::thread::spawn(move || {
+                while !stopped.load(Ordering::Relaxed) {
+                    let request = server.recv_timeout(WAIT_CHECK_INTERVAL);
+                    let mut sessions = sessions.lock().unwrap();
+                    if let Ok(Some(request)) = request {
+                        handle_request(request, &mut sessions);
+                    }
+                    sessions.answer_waiting();
+                }
+            })
+        };
+        Ok(GameServer {
+            server,
+            sessions,
+            stopped,
+            handle: Some(handle),
+        })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.server
+            .server_addr()
+            .to_ip()
+            .expect("The game server listens on an IP address")
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr())
+    }
+
+    // The base URL to pass to the clients of a session
+    pub fn session_url(&self, id: &str) -> String {
+        match id {
+            DEFAULT_SESSION => self.url(),
+            _ => format!("{}/games/{}", self.url(), id),
+        }
+    }
+
+    // Starts a new game in the session with the given ID, replacing any game it had
+    pub fn open_session(&self, id: &str, start: State, opponent: Option<Color>) {
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions
+            .games
+            .insert(id.to_string(), Session::new(start, opponent));
+    }
+
+    // Starts a new game in a new session and returns its ID
+    pub fn create_session(&self, start: State, opponent: Option<Color>) -> String {
+        self.sessions
+            .lock()
+            .unwrap()
+            .create(Session::new(start, opponent))
+    }
+
+    // The current state of the game in the session
+    pub fn state(&self, id: &str) -> Option<State> {
+        let sessions = self.sessions.lock().unwrap();
+        sessions.games.get(id).map(|session| session.state)
+    }
+
+    // Blocks while the server keeps running
+    pub fn wait(mut self) {
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for GameServer {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Relaxed);
+        self.server.unblock();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Sessions {
+    fn create(&mut self, session: Session) -> String {
+        let id = loop {
+            self.next_id += 1;
+            let id = self.next_id.to_string();
+            if !self.games.contains_key(&id) {
+                break id;
+            }
+        };
+        self.games.insert(id.clone(), session);
+        id
+    }
+
+    fn get(&mut self, id: &str) -> Result<&mut Session, (u16, String)> {
+        self.games
+            .get_mut(id)
+            .ok_or_else(|| (404, format!("Unknown game {id}")))
+    }
+
+    // Answers the held requests whose player got the turn, whose deadline passed
+    // or whose session was closed
+    fn answer_waiting(&mut self) {
+        let now = Instant::now();
+        let (ready, waiting) =
+            std::mem::take(&mut self.waiting)
+                .into_iter()
+                .partition(
+                    |waiting: &WaitingRequest| match self.games.get(&waiting.session) {
+                        Some(session) => {
+                            session.state.next_turn == waiting.color || now >= waiting.deadline
+                        }
+                        None => true,
+                    },
+                );
+        self.waiting = waiting;
+        for waiting in ready {
+            let result = match self.games.get(&waiting.session) {
+                Some(session) => Ok(protocol_color(session.state.next_turn).to_string()),
+                None => Err((404, format!("Unknown game {}", waiting.session))),
+            };
+            respond(waiting.request, result);
+        }
+    }
+}
+
+impl Session {
+    fn new(start: State, opponent: Option<Color>) -> Session {
+        let mut session = Session {
+            state: start,
+            opponent,
+        };
+        session.play_random();
+        session
+    }
+
+    // Answers one of the upstream server's endpoints for this game
+    fn handle(
+        &mut self,
+        method: &Method,
+        parts: &[&str],
+        query: &str,
+    ) -> Result<Reply, (u16, String)> {
+        let body = match (method, parts) {
+            // Once the game is over the player who didn't end it keeps the turn,
+            // so it fetches the final board
+            (Method::Get, ["turn"]) => protocol_color(self.state.next_turn).to_string(),
+            (Method::Get, ["board"]) => board_json(&self.state).to_string(),
+            (Method::Get, ["setChoice", x, y, player]) => {
+                self.play_move(player, Some((*x, *y)))?;
+                String::new()
+            }
+            (Method::Get, ["skipTurn", player]) => {
+                self.play_move(player, None)?;
+                String::new()
+            }
+            (Method::Post, ["AIStatus", _, _, _]) => String::new(),
+            (Method::Get, ["waitTurn", player]) => {
+                let timeout = match query.strip_prefix("timeout=") {
+                    Some(seconds) => seconds
+                        .parse()
+                        .ok()
+                        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+                        .ok_or_else(|| (400, format!("Invalid timeout {seconds}")))?,
+                    None => DEFAULT_WAIT_TIMEOUT,
+                };
+                return Ok(Reply::WaitForTurn(player_color(player)?, timeout));
+            }
+            _ => return Err((404, format!("Unknown request /{}", parts.join("/")))),
+        };
+        self.play_random();
+        Ok(Reply::Body(body))
+    }
+
+    // Plays the move of a client, or skips its turn when no position is given
+    fn play_move(
+        &mut self,
+        player: &str,
+        position: Option<(&str, &str)>,
+    ) -> Result<(), (u16, String)> {
+        let color = player_color(player)?;
+        if color != self.state.next_turn {
+            return Err((400, format!("It is not {player}'s turn")));
+        }
+        match position {
+            Some((x, y)) => {
+                let (Ok(x), Ok(y)) = (x.parse(), y.parse()) else {
+                    return Err((400, format!("Invalid position {x}/{y}")));
+                };
+                let action = Action::new(color, Position::new(x, y));
+                self.state = self
+                    .state
+                    .try_action(&action)
+                    .map_err(|e| (400, e.to_string()))?;
+            }
+            None if self.state.get_actions().is_empty() || self.state.is_game_over() => {
+                self.state = self.state.do_action(None);
+            }
+            None => return Err((400, "Can't skip while there are valid moves".to_string())),
+        }
+        Ok(())
+    }
+
+    // Plays random moves for the opponent until it is the client's turn or the game is over
+    fn play_random(&mut self) {
+        let Some(opponent) = self.opponent else {
+            return;
+        };
+        while self.state.next_turn == opponent && !self.state.is_game_over() {
+            let actions = self.state.get_actions();
+            let action = match actions.is_empty() {
+                true => None,
+                false => Some(actions[rand::thread_rng().gen_range(0..actions.len())].clone()),
+            };
+            self.state = self.state.do_action(action);
+        }
+    }
+}
+
+// Encodes the state like the game server, the board is indexed [x][y] with 1 for white,
+// 0 for black and -1 for empty fields, "turn" is true when black is next
+// "skipped" tells whether the previous player passed, the upstream server doesn't send it
+pub fn board_json(state: &State) -> serde_json::Value {
+    let size = state.board_size();
+    let board: Vec<Vec<i64>> = (0..size)
+        .map(|x| {
+            (0..size)
+                .map(|y| match state.disc_at(Position::new(x, y)) {
+                    Some(Color::WHITE) => 1,
+                    Some(Color::BLACK) => 0,
+                    None => -1,
+                })
+                .collect()
+        })
+        .collect();
+    serde_json::json!({
+        "board": board,
+        "turn": state.next_turn == Color::BLACK,
+        "skipped": state.prev_player_skipped,
+    })
+}
+
+fn handle_request(request: Request, sessions: &mut Sessions) {
+    let url = request.url().to_string();
+    let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+    let parts: Vec<&str> = path.trim_matches('/').split('/').collect();
+    let session = match parts.as_slice() {
+        ["games", id, _, ..] => id,
+        _ => DEFAULT_SESSION,
+    };
+    let reply = match (request.method(), parts.as_slice()) {
+        (Method::Get, ["games"]) => {
+            let mut ids: Vec<&String> = sessions.games.keys().collect();
+            ids.sort();
+            Ok(Reply::Body(serde_json::json!(ids).to_string()))
+        }
+        (Method::Post, ["games"]) => {
+            session_from_query(query).map(|new_session| Reply::Body(sessions.create(new_session)))
+        }
+        (Method::Delete, ["games", id]) => match sessions.games.remove(*id) {
+            Some(_) => Ok(Reply::Body(String::new())),
+            None => Err((404, format!("Unknown game {id}"))),
+        },
+        (method, ["games", id, parts @ ..]) => sessions
+            .get(id)
+            .and_then(|session| session.handle(method, parts, query)),
+        (method, parts) => sessions
+            .get(DEFAULT_SESSION)
+            .and_then(|session| session.handle(method, parts, query)),
+    };
+    match reply {
+        Ok(Reply::Body(body)) => respond(request, Ok(body)),
+        Ok(Reply::WaitForTurn(color, timeout)) => sessions.waiting.push(WaitingRequest {
+            request,
+            session: session.to_string(),
+            color,
+            deadline: Instant::now() + timeout,
+        }),
+        Err(e) => respond(request, Err(e)),
+    }
+}
+
+fn respond(request: Request, result: Result<String, (u16, String)>) {
+    let response = match result {
+        Ok(body) => Response::from_string(body),
+        Err((code, body)) => Response::from_string(body).with_status_code(code),
+    };
+    let _ = request.respond(response);
+}
+
+// The color of a player in the server's protocol
+fn player_color(player: &str) -> Result<Color, (u16, String)> {
+    match player {
+        "false" => Ok(Color::BLACK),
+        "true" => Ok(Color::WHITE),
+        _ => Err((400, format!("Unknown player {player}"))),
+    }
+}
+
+// Reads the starting state and opponent of a new session from "key=value" pairs joined by '&'
+// Keys and values are percent-encoded like a form, so a space is "%20" or "+"
+fn session_from_query(query: &str) -> Result<Session, (u16, String)> {
+    let mut position = None;
+    let mut handicap = None;
+    let mut opponent = None;
+    for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+        match key.as_ref() {
+            "position" => position = Some(value),
+            "handicap" => handicap = Some(value),
+            "opponent" => {
+                opponent = match value.to_lowercase().as_str() {
+                    "false" | "b" | "black" => Some(Color::BLACK),
+                    "true" | "w" | "white" => Some(Color::WHITE),
+                    _ => return Err((400, format!("Unknown opponent {value}"))),
+                }
+            }
+            _ => return Err((400, format!("Unknown parameter {key}"))),
+        }
+    }
+    let start = starting_state(position.as_deref(), handicap.as_deref())
+        .map_err(|e| (400, e.to_string()))?;
+    Ok(Session::new(start, opponent))
+}
+
+#[cfg(test)]
+mod server_tests {
+    use super::*;
+    use crate::othello::parse_state;
+
+    #[test]
+    fn test_board_json_round_trip() {
+        let state: State = State::new();
+        let state = state.do_action(state.get_actions().first().cloned());
+        assert_eq!(parse_state(&board_json(&state)), Ok(state));
+    }
+    #[test]
+    fn test_session_from_query() {
+        let session = session_from_query("").unwrap();
+        assert_eq!(session.state, State::new());
+        assert_eq!(session.opponent, None);
+
+        // A black opponent moves as soon as the session starts
+        let session = session_from_query("opponent=black&handicap=white:1").unwrap();
+        assert_eq!(session.opponent, Some(Color::BLACK));
+        assert_eq!(session.state.next_turn, Color::WHITE);
+        assert_eq!(
+            session.state.count_discs(Color::BLACK) + session.state.count_discs(Color::WHITE),
+            6
+        );
+
+        // Positions and handicaps are percent-decoded, with "+" as a space
+        let start: State = State::new();
+        let state = start.do_action(start.get_actions().first().cloned());
+        let position = state.to_string();
+        for encoded in [position.replace(' ', "%20"), position.replace(' ', "+")] {
+            let session = session_from_query(&format!("position={encoded}")).unwrap();
+            assert_eq!(session.state, state);
+        }
+        let session = session_from_query("handicap=white%3A1").unwrap();
+        assert_eq!(
+            session.state,
+            session_from_query("handicap=white:1").unwrap().state
+        );
+        assert_ne!(session.state, start);
+
+        assert!(session_from_query("opponent=red").is_err());
+        assert!(session_from_query("position=XO").is_err());
+        assert!(session_from_query("level=9").is_err());
+    }
+}
@@ -0,0 +1,241 @@
+use crate::mcts::{SearchProgress, StopCondition, MCTS};
+use crate::othello::{Action, Color, State};
+use std::time::{Duration, Instant};
+
+// Time kept back on the clock for network delays and overhead outside the search
+const SAFETY_MARGIN: Duration = Duration::from_millis(50);
+// Share of the increment spent on top of the remaining time's share
+const INCREMENT_SHARE: f64 = 0.8;
+// Contested moves may keep searching up to this multiple of the target time
+const MAX_TARGET_FACTOR: f64 = 3.0;
+// A critical move can't use more than this share of the remaining time
+const MAX_REMAINING_SHARE: f64 = 0.5;
+// The best move is contested while the second best has at least this share of its visits
+const CLOSE_RATIO: f64 = 0.8;
+// Visits of the root needed before the visit counts are trusted to stop early
+const MIN_ROOT_VISITS: usize = 100;
+
+// How long the AI searches for each of its moves
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchLimit {
+    // A number of iterations for the first move, growing by 2% every move
+    Iterations(usize),
+    // A game clock split over the moves by the time manager
+    Clock(TimeManager),
+}
+
+impl SearchLimit {
+    // Searches for the next move within the limit, then grows the number of iterations
+    // or takes the time of the search off the clock
+    #[allow(clippy::result_unit_err)]
+    pub fn search<const N: usize>(
+        &mut self,
+        mcts: &mut MCTS<N>,
+        state: State<N>,
+        send_status: impl Fn(usize, usize, &Color),
+    ) -> Result<Action, ()> {
+        match self {
+            SearchLimit::Iterations(iterations) => {
+                let choice = mcts.search(state, *iterations, send_status);
+                // Gives the ai 2% more iterations every round to balance the game simulations
+                // being shorter
+                *iterations += *iterations / 50;
+                choice
+            }
+            SearchLimit::Clock(clock) => {
+                let search = clock.start_move(state.remaining_moves as usize);
+                let (choice, _) = mcts.search_until(state, search);
+                clock.finish_move(search.start.elapsed());
+                choice
+            }
+        }
+    }
+}
+
+// The time planned for a single move
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MoveBudget {
+    // Time normally spent on the move
+    pub target: Duration,
+    // Time the move may take if the best moves are close after the target time
+    pub maximum: Duration,
+}
+
+// Splits a game clock with a fixed increment per move over the remaining moves
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeManager {
+    remaining: Duration,
+    increment: Duration,
+}
+
+impl TimeManager {
+    pub fn new(remaining: Duration, increment: Duration) -> TimeManager {
+        TimeManager {
+            remaining,
+            increment,
+        }
+    }
+
+    pub fn remaining(&self) -> Duration {
+        self.remaining
+    }
+
+    // Sets the remaining time, e.g. when the opponent's side reports the clock
+    pub fn set_remaining(&mut self, remaining: Duration) {
+        self.remaining = remaining;
+    }
+
+    // Plans the next move from the number of empty fields, which bounds the moves left
+    // Each player makes about half of them, one spare move keeps time for the end
+    pub fn budget(&self, empties: usize) -> MoveBudget {
+        let available = self.remaining.saturating_sub(SAFETY_MARGIN);
+        let moves_to_go = empties.div_ceil(2) as u32 + 1;
+        let target =
+            (available / moves_to_go + self.increment.mul_f64(INCREMENT_SHARE)).min(available);
+        let maximum = target
+            .mul_f64(MAX_TARGET_FACTOR)
+            .min(available.mul_f64(MAX_REMAINING_SHARE))
+            .max(target);
+        MoveBudget { target, maximum }
+    }
+
+    // Starts timing a move, the returned stop condition ends the search within its budget
+    pub fn start_move(&self, empties: usize) -> TimedSearch {
+        TimedSearch::new(self.budget(empties))
+    }
+
+    // Takes the time used by a move off the clock and adds the increment
+    pub fn finish_move(&mut self, used: Duration) {
+        self.remaining = self.remaining.saturating_sub(used) + self.increment;
+    }
+}
+
+// Stops a search when its move budget is spent
+// It stops early when the most visited move can't be overtaken before the target time,
+// and keeps searching past the target, up to the maximum, while the best two moves are close
+#[derive(Debug, Clone, Copy)]
+pub struct TimedSearch {
+    budget: MoveBudget,
+    start: Instant,
+}
+
+impl TimedSearch {
+    pub fn new(budget: MoveBudget) -> TimedSearch {
+        TimedSearch {
+            budget,
+            start: Instant::now(),
+        }
+    }
+
+    fn should_stop_after(&self, progress: &SearchProgress, elapsed: Duration) -> bool {
+        if elapsed >= self.budget.maximum {
+            return true;
+        }
+        if progress.root_visits < MIN_ROOT_VISITS {
+            return false;
+        }
+        let lead = progress.best_visits - progress.second_visits;
+        if elapsed >= self.budget.target {
+            return (progress.second_visits as f64) < progress.best_visits as f64 * CLOSE_RATIO;
+        }
+        // The visits expected until the target time, at the speed of the search so far
+        // Visits are counted rather than iterations, which may each add several of them
+        let speed = progress.visits as f64 / elapsed.as_secs_f64().max(f64::EPSILON);
+        let expected = speed * (self.budget.target - elapsed).as_secs_f64();
+        lead as f64 > expected
+    }
+}
+
+impl StopCondition for TimedSearch {
+    fn should_stop(&mut self, progress: &SearchProgress) -> bool {
+        self.should_stop_after(progress, self.start.elapsed())
+    }
+}
+
+#[cfg(test)]
+mod time_manager_tests {
+    use super::*;
+    use crate::mcts::MCTS;
+    use crate::othello::State;
+
+    fn progress(iterations: usize, best_visits: usize, second_visits: usize) -> SearchProgress {
+        SearchProgress {
+            iterations,
+            visits: iterations,
+            root_visits: iterations,
+            best_visits,
+            second_visits,
+        }
+    }
+
+    #[test]
+    fn test_budget() {
+        let mut manager = TimeManager::new(Duration::from_secs(60), Duration::from_secs(1));
+        let opening = manager.budget(60);
+        assert!(opening.target < Duration::from_secs(3));
+        assert!(opening.target <= opening.maximum);
+        assert!(opening.maximum <= Duration::from_secs(30));
+        // Fewer moves are left in the endgame, so each gets more time
+        assert!(manager.budget(10).target > opening.target);
+
+        manager.finish_move(Duration::from_secs(5));
+        assert_eq!(manager.remaining(), Duration::from_secs(56));
+
+        // The budget never exceeds the clock
+        let manager = TimeManager::new(Duration::from_millis(40), Duration::ZERO);
+        assert_eq!(manager.budget(2).maximum, Duration::ZERO);
+    }
+    #[test]
+    fn test_timed_search_stops() {
+        let second = Duration::from_secs(1);
+        let search = TimedSearch::new(MoveBudget {
+            target: second,
+            maximum: 3 * second,
+        });
+        // Too few visits to decide early, but always stop at the maximum
+        assert!(!search.should_stop_after(&progress(10, 10, 0), second / 10));
+        assert!(search.should_stop_after(&progress(10, 5, 5), 3 * second));
+        // A lead that can't be caught up before the target time stops early
+        assert!(search.should_stop_after(&progress(1000, 900, 50), second * 4 / 5));
+        assert!(!search.should_stop_after(&progress(1000, 500, 400), second * 4 / 5));
+        // Close moves search past the target, clear ones stop at it
+        assert!(!search.should_stop_after(&progress(2000, 900, 850), 2 * second));
+        assert!(search.should_stop_after(&progress(2000, 1500, 400), 2 * second));
+        // The speed is measured in visits, which iterations evaluating several leaves or games
+        // add more of at once
+        let batched = |best_visits, second_visits| SearchProgress {
+            visits: 1000,
+            root_visits: 1000,
+            ..progress(100, best_visits, second_visits)
+        };
+        assert!(search.should_stop_after(&batched(900, 50), second * 4 / 5));
+        assert!(!search.should_stop_after(&batched(500, 300), second * 4 / 5));
+    }
+    #[test]
+    fn test_timed_mcts_search() {
+        let mut mcts: MCTS = MCTS::new(Color::BLACK, 1.0);
+        let budget = MoveBudget {
+            target: Duration::from_millis(50),
+            maximum: Duration::from_millis(100),
+        };
+        let start = Instant::now();
+        let (choice, iterations) = mcts.search_until(State::new(), TimedSearch::new(budget));
+        assert!(choice.is_ok());
+        assert!(iterations > 0);
+        assert!(start.elapsed() < Duration::from_millis(500));
+    }
+    #[test]
+    fn test_search_without_time() {
+        // Black has 4 legal moves but the clock is within the safety margin
+        let state: State = State::new();
+        for remaining in [Duration::ZERO, Duration::from_millis(40)] {
+            let mut limit = SearchLimit::Clock(TimeManager::new(remaining, Duration::ZERO));
+            let mut mcts: MCTS = MCTS::new(Color::BLACK, 1.0);
+            let choice = limit.search(&mut mcts, state, |_, _, _| {}).unwrap();
+            assert!(state.get_actions().contains(&choice));
+        }
+        let mut mcts: MCTS = MCTS::new(Color::BLACK, 1.0);
+        let choice = SearchLimit::Iterations(0).search(&mut mcts, state, |_, _, _| {});
+        assert!(state.get_actions().contains(&choice.unwrap()));
+    }
+}
@@ -0,0 +1,214 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::Path;
+
+use crate::mcts::SearchReport;
+use crate::othello::{Color, State};
+use serde_json::json;
+
+// Number of symmetries of the board, the rotations and reflections leave the rules unchanged
+pub const SYMMETRIES: usize = 8;
+
+// A position of a self-play game and the share of the root's visits of every field
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample<const N: usize> {
+    pub state: State<N>,
+    // Visit shares row by row, 0 for the fields without a move
+    pub visits: Vec<f32>,
+}
+impl<const N: usize> Sample<N> {
+    // The sample of a searched position, None if the player had to pass
+    pub fn new(state: State<N>, report: &SearchReport) -> Option<Sample<N>> {
+        let mut visits = vec![0.0; N * N];
+        let mut total = 0;
+        for stats in &report.moves {
+            let position = stats.action.as_ref()?.position;
+            visits[position.y * N + position.x] = stats.visits as f32;
+            total += stats.visits;
+        }
+        if total == 0 {
+            return None;
+        }
+        visits.iter_mut().for_each(|v| *v /= total as f32);
+        Some(Sample { state, visits })
+    }
+}
+
+// The games written to a JSON lines file, one line per position and symmetry:
+// {"game", "records" (lines of the game), "board" (X, O and - row by row), "to_move",
+// "visits" (shares of the root's visits row by row), "result" (1, 0 or -1 for the side to move)}
+// A game without positions is written as {"game", "records": 0}, so it is finished as well
+pub struct TrainingData {
+    file: File,
+    symmetries: bool,
+}
+impl TrainingData {
+    // Opens the file to append games and returns the games already in it
+    // A game cut off by an interrupted run is removed, so it is played again
+    pub fn open(path: &Path, symmetries: bool) -> Result<(TrainingData, HashSet<usize>), String> {
+        let error = |e: std::io::Error| format!("{}: {e}", path.display());
+        let mut file = OpenOptions::new()
+            .read(true)
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(error)?;
+        let mut text = String::new();
+        file.read_to_string(&mut text).map_err(error)?;
+        let (finished, length) = finished_games(&text);
+        file.set_len(length as u64).map_err(error)?;
+        Ok((TrainingData { file, symmetries }, finished))
+    }
+
+    // Appends all positions of a game at once, so a game is either complete or at the end of the file
+    pub fn write_game<const N: usize>(
+        &mut self,
+        game: usize,
+        samples: &[Sample<N>],
+        winner: Option<Color>,
+    ) -> Result<(), String> {
+        let symmetries = if self.symmetries { SYMMETRIES } else { 1 };
+        let records = samples.len() * symmetries;
+        let mut lines = String::new();
+        if samples.is_empty() {
+            lines = json!({ "game": game, "records": 0 }).to_string() + "\n";
+        }
+        for sample in samples {
+            let result = match winner {
+                Some(color) if color == sample.state.next_turn => 1,
+                Some(_) => -1,
+                None => 0,
+            };
+            let to_move = match sample.state.next_turn {
+                Color::BLACK => "black",
+                Color::WHITE => "white",
+            };
+            let board: Vec<char> = sample.state.to_string().chars().take(N * N).collect();
+            // Four decimals keep the lines short
+            let visits: Vec<f64> = sample
+                .visits
+                .iter()
+                .map(|&v| (v as f64 * 10_000.0).round() / 10_000.0)
+                .collect();
+            for symmetry in 0..symmetries {
+                let record = json!({
+                    "game": game,
+                    "records": records,
+                    "board": transform::<N, _>(&board, symmetry).into_iter().collect::<String>(),
+                    "to_move": to_move,
+                    "visits": transform::<N, _>(&visits, symmetry),
+                    "result": result,
+                });
+                lines.push_str(&record.to_string());
+                lines.push('\n');
+            }
+        }
+        self.file
+            .write_all(lines.as_bytes())
+            .and_then(|_| self.file.flush())
+            .map_err(|e| format!("Couldn't write game {game}: {e}"))
+    }
+}
+
+// The games with all of their lines and the length of the file up to the last of them
+fn finished_games(text: &str) -> (HashSet<usize>, usize) {
+    let mut finished = HashSet::new();
+    let mut written: HashMap<u64, u64> = HashMap::new();
+    let mut length = 0;
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        offset += line.len();
+        let Ok(record) = serde_json::from_str::<serde_json::Value>(line) else {
+            break;
+        };
+        let (Some(game), Some(records)) = (record["game"].as_u64(), record["records"].as_u64())
+        else {
+            break;
+        };
+        let count = written.entry(game).or_default();
+        *count += 1;
+        // The marker of a game without positions is its only line
+        if *count == records.max(1) && line.ends_with('\n') {
+            finished.insert(game as usize);
+            length = offset;
+        }
+    }
+    (finished, length)
+}
+
+// Maps the fields of a board given row by row to one of its symmetries,
+// 0 keeps the board, bit 2 mirrors it on the diagonal, bit 0 flips the columns and bit 1 the rows
+pub fn transform<const N: usize, T: Copy>(fields: &[T], symmetry: usize) -> Vec<T> {
+    let mut transformed = fields.to_vec();
+    for y in 0..N {
+        for x in 0..N {
+            let (mut tx, mut ty) = if symmetry & 4 != 0 { (y, x) } else { (x, y) };
+            if symmetry & 1 != 0 {
+                tx = N - 1 - tx;
+            }
+            if symmetry & 2 != 0 {
+                ty = N - 1 - ty;
+            }
+            transformed[ty * N + tx] = fields[y * N + x];
+        }
+    }
+    transformed
+}
+
+#[cfg(test)]
+mod training_tests {
+    use super::*;
+    use crate::mcts::MCTS;
+
+    #[test]
+    fn test_symmetries_keep_the_moves() {
+        let state: State<4> = "OO-O OXOX XO-O -OXO O".parse().unwrap();
+        let board: Vec<char> = state.to_string().chars().take(16).collect();
+        let mut moves = vec![0; 16];
+        for action in state.get_actions() {
+            moves[action.position.y * 4 + action.position.x] = 1;
+        }
+        for symmetry in 0..SYMMETRIES {
+            let board: String = transform::<4, _>(&board, symmetry).into_iter().collect();
+            let transformed: State<4> = format!("{board} O").parse().unwrap();
+            let mut transformed_moves = vec![0; 16];
+            for action in transformed.get_actions() {
+                transformed_moves[action.position.y * 4 + action.position.x] = 1;
+            }
+            assert_eq!(transformed_moves, transform::<4, _>(&moves, symmetry));
+        }
+    }
+    #[test]
+    fn test_resume_after_interruption() {
+        let path = std::env::temp_dir().join(format!("training-{}.jsonl", std::process::id()));
+        let state: State<4> = "OO-O OXOX XO-O -OXO O".parse().unwrap();
+        let mut mcts: MCTS<4> = MCTS::new(Color::WHITE, 1.0).with_seed(1);
+        let (_, report) = mcts.search_with_report(state, 50, |_, _, _| {});
+        let sample = Sample::new(state, &report).unwrap();
+        assert!((sample.visits.iter().sum::<f32>() - 1.0).abs() < 1e-5);
+
+        let (mut data, finished) = TrainingData::open(&path, true).unwrap();
+        assert!(finished.is_empty());
+        data.write_game(0, std::slice::from_ref(&sample), Some(Color::WHITE))
+            .unwrap();
+        // A game in which every position was a pass still counts as finished
+        data.write_game::<4>(1, &[], None).unwrap();
+        data.write_game(2, &[sample.clone(), sample], None).unwrap();
+        drop(data);
+        // Cut the last game off in the middle of a line
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(text.lines().count(), 8 + 1 + 16);
+        std::fs::write(&path, &text[..text.len() - 100]).unwrap();
+
+        let (_, finished) = TrainingData::open(&path, true).unwrap();
+        assert_eq!(finished, HashSet::from([0, 1]));
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(text.lines().count(), 8 + 1);
+        let record: serde_json::Value = serde_json::from_str(text.lines().next().unwrap()).unwrap();
+        assert_eq!(record["board"], "OO-OOXOXXO-O-OXO");
+        assert_eq!(record["to_move"], "white");
+        assert_eq!(record["result"], 1);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,270 @@
+use std::io::{IsTerminal, Stdout, Write};
+
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::style::{
+    Color as TermColor, Print, ResetColor, SetBackgroundColor, SetForegroundColor,
+};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+
+use crate::console_game::{console_game, format_action, ConsoleOptions, Player};
+use rusty_othello_ai::mcts::MCTS;
+use rusty_othello_ai::othello::{caculate_win, Action, Color, Position, State, BOARD_SIZE};
+use rusty_othello_ai::render::flipped_discs;
+
+// Number of iterations used when looking up a hint for the player
+const HINT_ITERATIONS: usize = 10_000;
+// Number of moves shown in the history panel
+const HISTORY_LINES: usize = 16;
+// Screen column where the side panel starts
+const PANEL_COLUMN: u16 = 4 + 3 * BOARD_SIZE as u16 + 4;
+
+// Puts the terminal in raw mode on an alternate screen and restores it when dropped
+struct TerminalGuard;
+impl TerminalGuard {
+    fn new(stdout: &mut Stdout) -> std::io::Result<TerminalGuard> {
+        terminal::enable_raw_mode()?;
+        execute!(stdout, EnterAlternateScreen, Hide)?;
+        Ok(TerminalGuard)
+    }
+}
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = execute!(std::io::stdout(), Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+struct TuiGame {
+    state: State,
+    cursor: Position,
+    last_move: Option<Position>,
+    flipped: Vec<Position>,
+    history: Vec<String>,
+    message: String,
+}
+
+impl TuiGame {
+    fn new(state: State) -> TuiGame {
+        let center = BOARD_SIZE / 2 - 1;
+        TuiGame {
+            state,
+            cursor: Position::new(center, center),
+            last_move: None,
+            flipped: Vec::new(),
+            history: Vec::new(),
+            message: String::new(),
+        }
+    }
+
+    // Moves to the given state, remembering the move and the discs it flipped
+    fn play(&mut self, action: Option<Action>, new_state: State) {
+        let color = match self.state.next_turn {
+            Color::BLACK => "B",
+            Color::WHITE => "W",
+        };
+        self.history.push(format!(
+            "{:>2}. {} {}",
+            self.history.len() + 1,
+            color,
+            format_action(action.as_ref())
+        ));
+        self.last_move = action.map(|a| a.position);
+        self.flipped = flipped_discs(&self.state, &new_state);
+        self.state = new_state;
+    }
+
+    fn move_cursor(&mut self, dx: isize, dy: isize) {
+        let wrap = |value: usize, delta: isize| {
+            (value as isize + delta).rem_euclid(BOARD_SIZE as isize) as usize
+        };
+        self.cursor = Position::new(wrap(self.cursor.x, dx), wrap(self.cursor.y, dy));
+    }
+
+    fn draw(&self, stdout: &mut Stdout) -> std::io::Result<()> {
+        queue!(
+            stdout,
+            Clear(ClearType::All),
+            MoveTo(0, 0),
+            Print("Rusty Othello")
+        )?;
+        let header: String = (0..BOARD_SIZE).map(|x| format!(" {x} ")).collect();
+        queue!(stdout, MoveTo(2, 2), Print(header))?;
+        let legal: Vec<Position> = self
+            .state
+            .get_actions()
+            .iter()
+            .map(|a| a.position)
+            .collect();
+        for y in 0..BOARD_SIZE {
+            queue!(stdout, MoveTo(0, 3 + y as u16), Print(format!("{y} ")))?;
+            for x in 0..BOARD_SIZE {
+                let pos = Position::new(x, y);
+                let background = if pos == self.cursor {
+                    TermColor::Blue
+                } else if Some(pos) == self.last_move {
+                    TermColor::DarkRed
+                } else if self.flipped.contains(&pos) {
+                    TermColor::DarkYellow
+                } else {
+                    TermColor::DarkGreen
+                };
+                let (foreground, symbol) = match self.state.disc_at(pos) {
+                    Some(Color::BLACK) => (TermColor::Black, " ● "),
+                    Some(Color::WHITE) => (TermColor::White, " ● "),
+                    None if legal.contains(&pos) => (TermColor::Yellow, " · "),
+                    None => (TermColor::DarkGreen, "   "),
+                };
+                queue!(
+                    stdout,
+                    SetBackgroundColor(background),
+                    SetForegroundColor(foreground),
+                    Print(symbol),
+                    ResetColor
+                )?;
+            }
+        }
+
+        let next = match self.state.next_turn {
+            Color::BLACK => "Black",
+            Color::WHITE => "White",
+        };
+        let panel = [
+            format!("Black: {:>2}", self.state.count_discs(Color::BLACK)),
+            format!("White: {:>2}", self.state.count_discs(Color::WHITE)),
+            format!("Next:  {next}"),
+            String::new(),
+            "History".to_string(),
+        ];
+        for (i, line) in panel.iter().enumerate() {
+            queue!(stdout, MoveTo(PANEL_COLUMN, 2 + i as u16), Print(line))?;
+        }
+        let skipped = self.history.len().saturating_sub(HISTORY_LINES);
+        for (i, line) in self.history.iter().skip(skipped).enumerate() {
+            queue!(
+                stdout,
+                MoveTo(PANEL_COLUMN, 2 + panel.len() as u16 + i as u16),
+                Print(line)
+            )?;
+        }
+
+        let footer = 4 + BOARD_SIZE as u16;
+        queue!(
+            stdout,
+            MoveTo(0, footer),
+            Print(&self.message),
+            MoveTo(0, footer + 2),
+            Print("Arrows: move  Enter/Space: play  s: skip  h: hint  q: quit")
+        )?;
+        stdout.flush()
+    }
+}
+
+// Plays a console game in a full screen terminal UI
+// Falls back to the line based console game when stdout isn't a terminal
+pub fn tui_game(options: ConsoleOptions) {
+    if !std::io::stdout().is_terminal() {
+        return console_game(options);
+    }
+    if let Err(e) = run(options) {
+        eprintln!("Terminal error: {e}");
+    }
+}
+
+fn run(options: ConsoleOptions) -> std::io::Result<()> {
+    let mut stdout = std::io::stdout();
+    let _guard = TerminalGuard::new(&mut stdout)?;
+    let (mut black, mut white) = Player::for_options(&options);
+    let mut analysis = MCTS::new(Color::BLACK, options.exploration);
+    let mut game = TuiGame::new(options.start);
+    let dev_null = |_a: usize, _b: usize, _c: &Color| {};
+
+    while game.state.remaining_moves > 0 {
+        let (player, opponent) = match game.state.next_turn {
+            Color::BLACK => (&mut black, &mut white),
+            Color::WHITE => (&mut white, &mut black),
+        };
+        match player {
+            Player::Ai(mcts, ai_iterations) => {
+                game.message = "AI is thinking...".to_string();
+                game.draw(&mut stdout)?;
+                let action = mcts.search(game.state, *ai_iterations, dev_null).ok();
+                *ai_iterations += *ai_iterations / 100;
+                game.message.clear();
+                game.play(action.clone(), game.state.do_action(action));
+            }
+            Player::Human => {
+                game.draw(&mut stdout)?;
+                let event = match opponent.ponderer() {
+                    Some(mcts) => mcts.ponder_while(game.state, event::read).0,
+                    None => event::read(),
+                };
+                let Event::Key(key) = event? else {
+                    continue;
+                };
+                if key.kind != KeyEventKind::Press {
+                    continue;
+                }
+                match key.code {
+                    KeyCode::Up => game.move_cursor(0, -1),
+                    KeyCode::Down => game.move_cursor(0, 1),
+                    KeyCode::Left => game.move_cursor(-1, 0),
+                    KeyCode::Right => game.move_cursor(1, 0),
+                    KeyCode::Enter | KeyCode::Char(' ') => {
+                        let action = Action::new(game.state.next_turn, game.cursor);
+                        match game.state.try_action(&action) {
+                            Ok(new_state) => {
+                                game.message.clear();
+                                game.play(Some(action), new_state);
+                            }
+                            Err(e) => game.message = format!("Invalid move: {e}"),
+                        }
+                    }
+                    KeyCode::Char('s') => {
+                        if game.state.get_actions().is_empty() {
+                            game.message.clear();
+                            game.play(None, game.state.do_action(None));
+                        } else {
+                            game.message = "You can only skip without valid moves".to_string();
+                        }
+                    }
+                    KeyCode::Char('h') => {
+                        game.message = "Looking for a hint...".to_string();
+                        game.draw(&mut stdout)?;
+                        let (choice, report) =
+                            analysis.search_with_report(game.state, HINT_ITERATIONS, dev_null);
+                        game.message = match (choice, report.moves.first()) {
+                            (Ok(action), Some(best)) => {
+                                game.cursor = action.position;
+                                format!(
+                                    "Hint: {} (estimated win rate {:.1}%)",
+                                    format_action(Some(&action)),
+                                    best.win_rate() * 100.0
+                                )
+                            }
+                            _ => "No moves available, you have to skip".to_string(),
+                        };
+                    }
+                    KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    game.message = match caculate_win(game.state) {
+        Some(Color::WHITE) => "White wins! Press any key to exit",
+        Some(Color::BLACK) => "Black wins! Press any key to exit",
+        None => "Draw. Press any key to exit",
+    }
+    .to_string();
+    game.draw(&mut stdout)?;
+    loop {
+        if let Event::Key(key) = event::read()? {
+            if key.kind == KeyEventKind::Press {
+                return Ok(());
+            }
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use rusty_othello_ai::client::{ClientError, GameServerClient};
+use rusty_othello_ai::mcts::MCTS;
+use rusty_othello_ai::othello::{Action, Color, Position, State};
+use rusty_othello_ai::server::{GameServer, DEFAULT_SESSION};
+use rusty_othello_ai::time_manager::{SearchLimit, TimeManager};
+use std::time::{Duration, Instant};
+
+fn play(client: GameServerClient, color: Color) -> State {
+    let mut mcts = MCTS::new(color, 1.0);
+    client.play(color, &mut mcts, State::new(), SearchLimit::Iterations(100))
+}
+
+fn assert_finished(state: State, server_state: State) {
+    assert!(state.is_game_over());
+    assert_eq!(state.to_string(), server_state.to_string());
+}
+
+// Plays a full game of the AI against the server's random opponent
+fn play_against_random(ai_color: Color, long_polling: bool) {
+    let server = GameServer::start("127.0.0.1:0").unwrap();
+    server.open_session(DEFAULT_SESSION, State::new(), Some(ai_color.opponent()));
+    let client = GameServerClient::new(&server.url()).with_long_polling(long_polling);
+    let state = play(client, ai_color);
+    assert_finished(state, server.state(DEFAULT_SESSION).unwrap());
+}
+
+#[test]
+fn test_black_ai_against_random() {
+    play_against_random(Color::BLACK, true);
+}
+
+#[test]
+fn test_white_ai_against_random() {
+    play_against_random(Color::WHITE, true);
+}
+
+#[test]
+fn test_polling_ai_against_random() {
+    play_against_random(Color::WHITE, false);
+}
+
+#[test]
+fn test_ai_on_clock_against_random() {
+    let server = GameServer::start("127.0.0.1:0").unwrap();
+    server.open_session(DEFAULT_SESSION, State::new(), Some(Color::WHITE));
+    let client = GameServerClient::new(&server.url());
+    let mut mcts = MCTS::new(Color::BLACK, 1.0);
+    let clock = TimeManager::new(Duration::from_secs(2), Duration::from_millis(10));
+    let start = Instant::now();
+    let state = client.play(
+        Color::BLACK,
+        &mut mcts,
+        State::new(),
+        SearchLimit::Clock(clock),
+    );
+    assert_finished(state, server.state(DEFAULT_SESSION).unwrap());
+    // The clock only runs during the AI's turns, leave room for the requests and other tests
+    assert!(start.elapsed() < Duration::from_secs(10));
+}
+
+#[test]
+fn test_concurrent_ai_games() {
+    let server = GameServer::start("127.0.0.1:0").unwrap();
+    let ids: Vec<String> = (0..2)
+        .map(|_| server.create_session(State::new(), None))
+        .collect();
+    std::thread::scope(|scope| {
+        let games: Vec<_> = ids
+            .iter()
+            .map(|id| {
+                let client = GameServerClient::new(&server.session_url(id));
+                let black = scope.spawn({
+                    let client = client.clone();
+                    move || play(client, Color::BLACK)
+                });
+                let white = scope.spawn(move || play(client, Color::WHITE));
+                (id, black, white)
+            })
+            .collect();
+        for (id, black, white) in games {
+            let (black, white) = (black.join().unwrap(), white.join().unwrap());
+            let server_state = server.state(id).unwrap();
+            assert_finished(black, server_state);
+            assert_finished(white, server_state);
+        }
+    });
+}
+
+#[test]
+fn test_invalid_requests_are_rejected() {
+    let server = GameServer::start("127.0.0.1:0").unwrap();
+    let id = server.create_session(State::new(), None);
+    let client = GameServerClient::new(&server.session_url(&id));
+    assert_eq!(client.is_my_turn(Color::BLACK), Ok(true));
+    assert_eq!(client.is_my_turn(Color::WHITE), Ok(false));
+
+    // Wrong player, occupied field and skipping with valid moves
+    let action = Action::new(Color::WHITE, Position::new(2, 3));
+    assert!(matches!(
+        client.send_move(Color::WHITE, Some(&action)),
+        Err(ClientError::Status(400, _))
+    ));
+    let action = Action::new(Color::BLACK, Position::new(3, 3));
+    assert!(matches!(
+        client.send_move(Color::BLACK, Some(&action)),
+        Err(ClientError::Status(400, _))
+    ));
+    assert!(matches!(
+        client.send_move(Color::BLACK, None),
+        Err(ClientError::Status(400, _))
+    ));
+    assert_eq!(server.state(&id), Some(State::new()));
+
+    let action = Action::new(Color::BLACK, Position::new(2, 3));
+    client.send_move(Color::BLACK, Some(&action)).unwrap();
+    assert_eq!(
+        client.get_state().unwrap(),
+        State::new().do_action(Some(action))
+    );
+    assert_eq!(client.is_my_turn(Color::WHITE), Ok(true));
+    assert_eq!(client.send_status(1, 2, Color::WHITE), Ok(()));
+
+    // Sessions that don't exist, including the default one that was never opened
+    let missing = GameServerClient::new(&server.session_url("missing"));
+    assert!(matches!(
+        missing.is_my_turn(Color::BLACK),
+        Err(ClientError::Status(404, _))
+    ));
+    let default = GameServerClient::new(&server.url());
+    assert!(matches!(
+        default.get_state(),
+        Err(ClientError::Status(404, _))
+    ));
+}
+
+#[test]
+fn test_wait_for_turn() {
+    let server = GameServer::start("127.0.0.1:0").unwrap();
+    let id = server.create_session(State::new(), None);
+    let client = GameServerClient::new(&server.session_url(&id));
+    assert_eq!(client.wait_for_turn(Color::BLACK, Duration::ZERO), Ok(true));
+    assert_eq!(
+        client.wait_for_turn(Color::WHITE, Duration::from_millis(100)),
+        Ok(false)
+    );
+
+    // The request is answered as soon as the other player moves
+    std::thread::scope(|scope| {
+        let waiting = scope.spawn(|| client.wait_for_turn(Color::WHITE, Duration::from_secs(30)));
+        std::thread::sleep(Duration::from_millis(200));
+        assert!(!waiting.is_finished());
+        let action = Action::new(Color::BLACK, Position::new(2, 3));
+        client.send_move(Color::BLACK, Some(&action)).unwrap();
+        assert_eq!(waiting.join().unwrap(), Ok(true));
+    });
+
+    let missing = GameServerClient::new(&server.session_url("missing"));
+    assert!(matches!(
+        missing.wait_for_turn(Color::BLACK, Duration::ZERO),
+        Err(ClientError::Status(404, _))
+    ));
+}